@@ -0,0 +1,4010 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, HashMap};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+use glob::{glob, glob_with, MatchOptions};
+use indicatif::{ProgressBar, ProgressStyle};
+use notify::{RecursiveMode, Watcher};
+use rayon::prelude::*;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, info_span, warn};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Extensionless {
+    /// 包含所有不含空字节的无扩展名文件
+    Allow,
+    /// 跳过所有无扩展名文件
+    Deny,
+    /// 只包含内容为有效 UTF-8 文本的无扩展名文件
+    Detect,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InvalidUtf8 {
+    /// 报错并中止打包
+    Error,
+    /// 跳过该文件
+    Skip,
+    /// 按二进制文件处理，使用 base64 编码
+    Binary,
+    /// 用替换字符代替无效字节，并在文件头中标记 lossy-utf8
+    Lossy,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortOrder {
+    /// 按相对路径的字典序排序
+    Path,
+    /// 按相对路径自然排序，文件名中的数字按数值比较（file2 排在 file10 之前）
+    Natural,
+    /// 按文件大小从小到大排序
+    Size,
+    /// 按修改时间从旧到新排序
+    Mtime,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EolMode {
+    /// 保持原有换行符，还原后与原文件一致
+    Keep,
+    /// 统一转换为 LF
+    Lf,
+    /// 统一转换为 CRLF
+    Crlf,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputCompression {
+    /// 不压缩
+    None,
+    /// 使用 gzip 压缩，输出文件名追加 .gz
+    Gzip,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// 以 `###  trxx:路径` 分隔的 Markdown 代码块
+    Markdown,
+    /// JSON 数组，每个文件一个对象，便于程序处理
+    Json,
+    /// tar 归档，原样保存文件内容和权限，适合存档而不是给 LLM 阅读
+    Tar,
+    /// zip 压缩包，Windows 上可以直接打开
+    Zip,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RelpathStyle {
+    /// 相对于打包目录的路径
+    Relative,
+    /// 只保留文件名
+    Basename,
+    /// 绝对路径，还原时需要 --allow-absolute 才会写回原来的位置
+    Absolute,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LockMode {
+    /// 等待其他进程释放锁，直到超时
+    Wait,
+    /// 发现锁被占用时立即报错
+    Fail,
+}
+
+pub struct LockOptions {
+    pub mode: LockMode,
+    pub timeout: Duration,
+}
+
+#[derive(Default)]
+pub struct RevertOptions {
+    pub preserve_ctime: bool,
+    pub no_create_dirs: bool,
+    pub strict: bool,
+    pub preserve_mtime: bool,
+    // 还原到的目录，None 表示当前目录
+    pub output_dir: Option<PathBuf>,
+    // 允许绝对路径的文件头写回原来的位置
+    pub allow_absolute: bool,
+    // 只还原路径匹配这些 glob 模式的文件，为空时还原全部
+    pub only: Vec<String>,
+    // 文件头的前缀，None 表示默认的 `###  trxx:`
+    pub header_prefix: Option<String>,
+}
+
+impl RevertOptions {
+    fn header_prefix(&self) -> &str {
+        self.header_prefix.as_deref().unwrap_or(DEFAULT_HEADER_PREFIX)
+    }
+
+    // 文件路径本身或它所在的任意一级目录匹配 --only 即可
+    fn selects(&self, path: &str, only: &[glob::Pattern]) -> bool {
+        self.only.is_empty() || matches_path_or_parents(Path::new(path), only)
+    }
+}
+
+// 打包过程中的统计信息
+#[derive(Default)]
+struct PackStats {
+    // 实际打包的文件数和原始字节数
+    files: usize,
+    bytes: u64,
+    // 删除了行尾空白的文件数和行数
+    whitespace_files: usize,
+    whitespace_lines: usize,
+    // 按原因记录被跳过的文件
+    skipped: SkipReport,
+    // 每种语言的文件段落估算的 token 数
+    tokens: BTreeMap<String, usize>,
+    // --keep-going 时处理失败而跳过的文件
+    failed: Vec<(PathBuf, anyhow::Error)>,
+}
+
+impl PackStats {
+    // 合并单个文件的统计信息
+    fn merge(&mut self, other: PackStats) {
+        self.files += other.files;
+        self.bytes += other.bytes;
+        self.whitespace_files += other.whitespace_files;
+        self.whitespace_lines += other.whitespace_lines;
+        for (reason, paths) in other.skipped.0 {
+            self.skipped.0.entry(reason).or_default().extend(paths);
+        }
+        for (lang, tokens) in other.tokens {
+            *self.tokens.entry(lang).or_default() += tokens;
+        }
+        self.failed.extend(other.failed);
+    }
+
+    // 输出 token 总数和按语言的分布
+    fn print_tokens(&self, total: usize) {
+        eprintln!("估算 token 数: ~{}", format_count(total));
+        eprintln!("按语言:");
+        for (lang, tokens) in &self.tokens {
+            eprintln!("  {:<16} ~{}", lang, format_count(*tokens));
+        }
+    }
+}
+
+// 文件被跳过的原因
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipReason {
+    Ignored,
+    TooDeep,
+    Excluded,
+    Trxxignore,
+    Gitignore,
+    NotIncluded,
+    GitAuthor,
+    TestFilter,
+    Unsupported,
+    LongLine,
+    LanguageLimit,
+    InvalidUtf8,
+    Size,
+    BinaryFilter,
+    LanguageFilter,
+}
+
+impl SkipReason {
+    fn label(self) -> &'static str {
+        match self {
+            SkipReason::Ignored => "自动忽略的目录或文件",
+            SkipReason::TooDeep => "超出最大目录深度",
+            SkipReason::Excluded => "--exclude 排除",
+            SkipReason::Trxxignore => ".trxxignore 忽略",
+            SkipReason::Gitignore => ".gitignore 忽略",
+            SkipReason::NotIncluded => "不匹配 --include",
+            SkipReason::GitAuthor => "不是指定作者修改的文件",
+            SkipReason::TestFilter => "测试文件过滤",
+            SkipReason::Unsupported => "不支持的文件类型",
+            SkipReason::LongLine => "存在超长行",
+            SkipReason::LanguageLimit => "超出语言数量限制",
+            SkipReason::InvalidUtf8 => "非 UTF-8 编码",
+            SkipReason::BinaryFilter => "二进制文件过滤",
+            SkipReason::LanguageFilter => "不在 --lang-only 指定的语言中",
+            SkipReason::Size => "超出大小限制",
+        }
+    }
+}
+
+#[derive(Default)]
+struct SkipReport(BTreeMap<SkipReason, Vec<PathBuf>>);
+
+impl SkipReport {
+    // 每个被跳过的文件都在 -v 时输出原因
+    fn add(&mut self, reason: SkipReason, path: &Path) {
+        debug!("跳过 {}：{}", path.display(), reason.label());
+        self.0.entry(reason).or_default().push(path.to_path_buf());
+    }
+
+    // 没有要求详细统计时，只用一行日志说明各原因跳过的数量
+    fn log_summary(&self) {
+        let total: usize = self.0.values().map(Vec::len).sum();
+        if total == 0 {
+            return;
+        }
+        let counts: Vec<_> = self.0.iter()
+            .map(|(reason, paths)| format!("{} {}", reason.label(), paths.len()))
+            .collect();
+        info!("跳过了 {} 个文件（{}），使用 --list-skipped 或 -v 查看具体文件", total, counts.join("，"));
+    }
+
+    fn print(&self, root: &Path, list: bool) {
+        let total: usize = self.0.values().map(Vec::len).sum();
+        if total == 0 {
+            eprintln!("没有跳过任何文件");
+            return;
+        }
+        eprintln!("共跳过 {} 个文件：", total);
+        for (reason, paths) in &self.0 {
+            eprintln!("  {}: {}", reason.label(), paths.len());
+            if list {
+                for path in paths {
+                    eprintln!("    {}", path.strip_prefix(root).unwrap_or(path).display());
+                }
+            }
+        }
+    }
+}
+
+pub struct PackOptions {
+    pub lock: LockOptions,
+    pub relpath_style: RelpathStyle,
+    // 加在文件头相对路径前面的目录，例如 `backend`，使多个仓库打包到同一个文件时路径不冲突
+    pub root_prefix: Option<String>,
+    pub warn_mixed_eol: bool,
+    pub clipboard: bool,
+    pub include_binary_metadata: bool,
+    pub extensionless: Extensionless,
+    pub max_line_length: Option<usize>,
+    pub on_invalid_utf8: InvalidUtf8,
+    pub readme_first: bool,
+    pub compress_binary: bool,
+    pub split_file_over: Option<usize>,
+    pub suggest_ignores: bool,
+    pub normalize_whitespace: bool,
+    pub record_ctime: bool,
+    pub git_author: Option<String>,
+    // 是否根据文件头部的魔数判断文件类型
+    pub sniff: bool,
+    pub limit_per_language: Option<usize>,
+    // 只保留这些语言的文件（按扩展名映射表中的语言名匹配，不区分大小写），为空时不过滤
+    pub lang_only: Vec<String>,
+    pub path_comments: bool,
+    // 在文本文件的文件头中写入行数和字符数，还原时忽略
+    pub annotate: bool,
+    // 在文本文件的每一行前加上右对齐的行号，还原时去掉
+    pub line_numbers: bool,
+    pub estimate: bool,
+    pub include_lockfiles: bool,
+    pub include_output: bool,
+    pub output_repo_root: bool,
+    pub output: Option<PathBuf>,
+    pub sort: SortOrder,
+    pub header: bool,
+    pub deterministic: bool,
+    pub exclude_tests: bool,
+    pub tests_only: bool,
+    // 跳过所有二进制文件（包括图片）
+    pub no_binary: bool,
+    // 只打包二进制文件
+    pub binary_only: bool,
+    pub test_patterns: Vec<String>,
+    pub binary_placeholder: bool,
+    pub skip_report: bool,
+    pub list_skipped: bool,
+    pub gitignore: bool,
+    pub jobs: Option<usize>,
+    pub stdout: bool,
+    pub exclude: Vec<String>,
+    pub include: Vec<String>,
+    // 在内置映射之外追加的扩展名到语言的映射
+    pub extensions: HashMap<String, String>,
+    // 文本文件的大小上限，None 表示使用默认的 1MB（SVG 不受限制），Some(0) 表示不限制
+    pub max_size: Option<u64>,
+    // 小于该大小的文件被跳过（图片除外），0 表示不限制
+    pub min_size: u64,
+    // 二进制文件（包括图片）的大小上限，None 表示二进制文件与文本文件使用相同的规则，Some(0) 表示不限制
+    pub binary_max_size: Option<u64>,
+    // 相对于打包目录的最大深度，0 表示只打包根目录下的文件
+    pub max_depth: Option<usize>,
+    // 同时打包 .env、.github/ 等以点开头的文件和目录
+    pub hidden: bool,
+    pub metadata: bool,
+    pub checksum: bool,
+    // UTF-8 解码失败时是否尝试检测其他编码
+    pub encoding_detect: bool,
+    pub normalize_eol: EolMode,
+    pub compress: OutputCompression,
+    pub format: OutputFormat,
+    // 在打包内容开头写入文件清单
+    pub manifest: bool,
+    // 打包后输出 token 估算
+    pub tokens: bool,
+    // token 总数超过该值时给出警告
+    pub token_limit: Option<usize>,
+    // 按字节数或估算的 token 数把输出拆分成多个编号的文件
+    pub split_size: Option<u64>,
+    pub split_tokens: Option<usize>,
+    // 只检查和统计，不写入任何输出
+    pub dry_run: bool,
+    // 单个文件读取或解码失败时跳过该文件继续打包
+    pub keep_going: bool,
+    // 替换内置扩展名映射的外部映射
+    pub lang_map: Option<HashMap<String, String>>,
+    // 转义 markdown 文件中以 ``` 或 # 开头的行
+    pub escape_markdown: bool,
+    // 在打包文件中记录空目录，还原时重新创建
+    pub keep_empty_dirs: bool,
+    // 只打包列表文件中的路径，不遍历目录
+    pub files_from: Option<PathBuf>,
+    // 在标准错误中显示处理进度
+    pub progress: bool,
+    // 只打包与之前的打包文件相比新增或有变化的文件
+    pub since: Option<PathBuf>,
+    // 内容相同的文件只保留第一个，后面的只记录原文件路径
+    pub dedup: bool,
+    // 打包前在终端中勾选要打包的文件
+    pub interactive: bool,
+    // 删除源代码中的注释以节省 token
+    pub strip_comments: bool,
+    // 文件头的前缀，还原时需要使用相同的前缀
+    pub header_prefix: String,
+}
+
+/// 配置文件 .trxx.toml 的内容，命令行参数优先于配置文件
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub output: Option<PathBuf>,
+    pub exclude: Vec<String>,
+    pub include: Vec<String>,
+    pub jobs: Option<usize>,
+    // 与 --max-size 格式相同，例如 "4M"
+    pub max_size: Option<String>,
+    pub extensions: HashMap<String, String>,
+}
+
+// 打包目录下的默认配置文件名
+const CONFIG_FILE: &str = ".trxx.toml";
+
+impl Config {
+    /// 读取指定的配置文件；没有指定时读取打包目录下的 .trxx.toml，文件不存在时使用默认配置
+    pub fn load(dir: &Path, path: Option<&Path>) -> Result<Config> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let path = dir.join(CONFIG_FILE);
+                if !path.is_file() {
+                    return Ok(Config::default());
+                }
+                path
+            }
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("无法读取配置文件 {}", path.display()))?;
+        let config = toml::from_str(&content)
+            .with_context(|| format!("无法解析配置文件 {}", path.display()))?;
+        debug!("已加载配置文件 {}", path.display());
+        Ok(config)
+    }
+}
+
+// 默认的输出文件名
+const DEFAULT_OUTPUT: &str = "all_content.md";
+/// 默认的文件头前缀，文件头为 `###  trxx:路径`
+pub const DEFAULT_HEADER_PREFIX: &str = "###  trxx:";
+const DEFAULT_TAR_OUTPUT: &str = "all_content.tar";
+const DEFAULT_ZIP_OUTPUT: &str = "all_content.zip";
+
+// 与命令行不加任何参数时的行为一致
+impl Default for PackOptions {
+    fn default() -> Self {
+        PackOptions {
+            lock: LockOptions {
+                mode: LockMode::Wait,
+                timeout: Duration::from_secs(30),
+            },
+            relpath_style: RelpathStyle::Relative,
+            root_prefix: None,
+            warn_mixed_eol: false,
+            clipboard: false,
+            include_binary_metadata: false,
+            extensionless: Extensionless::Detect,
+            max_line_length: None,
+            on_invalid_utf8: InvalidUtf8::Error,
+            readme_first: false,
+            compress_binary: false,
+            split_file_over: None,
+            suggest_ignores: false,
+            normalize_whitespace: false,
+            record_ctime: false,
+            git_author: None,
+            sniff: true,
+            limit_per_language: None,
+            lang_only: Vec::new(),
+            annotate: false,
+            line_numbers: false,
+            path_comments: false,
+            estimate: false,
+            include_lockfiles: false,
+            include_output: false,
+            output_repo_root: false,
+            output: None,
+            sort: SortOrder::Path,
+            header: false,
+            deterministic: false,
+            exclude_tests: false,
+            tests_only: false,
+            no_binary: false,
+            binary_only: false,
+            test_patterns: Vec::new(),
+            binary_placeholder: false,
+            skip_report: false,
+            list_skipped: false,
+            gitignore: true,
+            jobs: None,
+            stdout: false,
+            exclude: Vec::new(),
+            include: Vec::new(),
+            extensions: HashMap::new(),
+            max_size: None,
+            min_size: 0,
+            binary_max_size: None,
+            max_depth: None,
+            hidden: false,
+            metadata: false,
+            checksum: false,
+            encoding_detect: true,
+            normalize_eol: EolMode::Keep,
+            compress: OutputCompression::None,
+            format: OutputFormat::Markdown,
+            manifest: false,
+            tokens: false,
+            token_limit: None,
+            split_size: None,
+            split_tokens: None,
+            dry_run: false,
+            keep_going: false,
+            lang_map: None,
+            escape_markdown: true,
+            keep_empty_dirs: false,
+            files_from: None,
+            progress: false,
+            since: None,
+            dedup: false,
+            interactive: false,
+            strip_comments: false,
+            header_prefix: DEFAULT_HEADER_PREFIX.to_string(),
+        }
+    }
+}
+
+impl PackOptions {
+    // 输出文件名，打包时会忽略同名文件
+    fn output_file_name(&self) -> &str {
+        self.output
+            .as_deref()
+            .and_then(Path::file_name)
+            .and_then(|n| n.to_str())
+            .unwrap_or(self.default_output())
+    }
+
+    // 没有指定 -o 时的输出文件名
+    fn default_output(&self) -> &'static str {
+        match self.format {
+            OutputFormat::Tar => DEFAULT_TAR_OUTPUT,
+            OutputFormat::Zip => DEFAULT_ZIP_OUTPUT,
+            _ => DEFAULT_OUTPUT,
+        }
+    }
+}
+
+// 总是忽略的目录
+const IGNORED_DIRS: &[&str] = &[".git", "target", "node_modules"];
+
+// 按 / 和 \ 拆分后逐段比较，Unix 上 Path::components 不会拆分 target\foo 这样的路径，
+// 逐段比较也不会误匹配 my-target-dir 这类目录
+fn in_ignored_dir(dir: &Path) -> bool {
+    dir.to_string_lossy().split(['/', '\\']).any(|name| IGNORED_DIRS.contains(&name))
+}
+
+// rel 是相对于打包目录的路径，打包目录本身位于 target 等目录下时不受影响
+fn should_ignore_path(rel: &Path, opts: &PackOptions) -> bool {
+    // 统一成 / 分隔，parent() 才能在 Unix 上取到 target\foo.txt 中的目录部分
+    let rel = PathBuf::from(rel.to_string_lossy().replace('\\', "/"));
+    // 检查是否包含需要忽略的目录
+    if rel.parent().is_some_and(in_ignored_dir) {
+        return true;
+    }
+
+    // 检查是否是需要忽略的文件
+    if let Some(file_name) = rel.file_name().and_then(|n| n.to_str()) {
+        if is_output_name(file_name, opts.output_file_name()) && !opts.include_output {
+            return true;
+        }
+        if file_name.ends_with(".lock") && !opts.include_lockfiles {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn load_extension_map() -> Result<HashMap<String, String>> {
+    let map_content = r#"{
+        "rs": "rust",
+        "json": "json",
+        "jsonc": "jsonc",
+        "json5": "json5",
+        "js": "javascript",
+        "ts": "typescript",
+        "py": "python",
+        "java": "java",
+        "cpp": "cpp",
+        "c": "c",
+        "go": "go",
+        "rb": "ruby",
+        "php": "php",
+        "html": "html",
+        "css": "css",
+        "md": "markdown",
+        "yaml": "yaml",
+        "yml": "yaml",
+        "toml": "toml",
+        "sh": "bash",
+        "bash": "bash",
+        "sql": "sql",
+        "vue": "vue",
+        "jsx": "jsx",
+        "tsx": "tsx",
+        "lua": "lua",
+        ".h": "c/c++ header",
+        ".conf": "conf",
+        ".ini": "ini",
+        ".txt": "text",
+        ".bat": "batch file",
+        ".ps1": "powershell",
+        ".env": "env",
+        ".gitignore": "gitignore",
+        "wxss": "css",
+        "wxml": "xml",
+        "ux": "html"
+    }"#;
+    
+    let map: HashMap<String, String> = serde_json::from_str(map_content)?;
+    Ok(map)
+}
+
+/// 读取外部的扩展名映射文件，格式与 `trxx dump-ext-map` 的输出相同
+pub fn load_lang_map(path: &Path) -> Result<HashMap<String, String>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("无法读取扩展名映射文件 {}", path.display()))?;
+    let map: HashMap<String, String> = serde_json::from_str(&content)
+        .with_context(|| format!("扩展名映射文件 {} 不是有效的 JSON 对象", path.display()))?;
+    Ok(map.into_iter().map(|(ext, lang)| (normalize_extension(&ext), lang)).collect())
+}
+
+// 内置映射（或替换它的外部映射）加上配置文件中追加的映射，追加的映射优先
+fn extension_map(opts: &PackOptions) -> Result<HashMap<String, String>> {
+    let mut map = match &opts.lang_map {
+        Some(map) => map.clone(),
+        None => load_extension_map()?,
+    };
+    for (ext, lang) in &opts.extensions {
+        map.insert(normalize_extension(ext), lang.clone());
+    }
+    Ok(map)
+}
+
+// 配置中的扩展名可以带前导点，统一转成小写
+fn normalize_extension(ext: &str) -> String {
+    ext.trim_start_matches('.').to_lowercase()
+}
+
+pub fn dump_extension_map(output: Option<&str>) -> Result<()> {
+    // 按扩展名排序，保证输出稳定，方便对比和编辑
+    let map: BTreeMap<String, String> = load_extension_map()?.into_iter().collect();
+    let json = serde_json::to_string_pretty(&map)?;
+
+    match output {
+        Some(path) => {
+            fs::write(path, format!("{}\n", json))
+                .with_context(|| format!("无法写入文件 {}", path))?;
+            info!("扩展名映射已写入 {}", path);
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+// 以 ```、# 开头的行在行首加一个反斜杠；原本以反斜杠开头的行也要加，还原时才能区分
+fn needs_markdown_escape(line: &str) -> bool {
+    line.starts_with("```") || line.starts_with('#') || line.starts_with('\\')
+}
+
+fn escape_markdown_content(content: &str, is_markdown: bool) -> String {
+    if !is_markdown {
+        return content.to_string();
+    }
+
+    // 逐行处理，保留行尾的 \r 和末尾的换行
+    content.split_inclusive('\n')
+        .map(|line| {
+            if needs_markdown_escape(line) {
+                format!("\\{}", line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect()
+}
+
+// 遍历目录下的所有文件和子目录；只有 hidden 时才包括 .env、.github/ 等以点开头的条目，
+// 两种情况下 .git 等需要忽略的目录都由 should_ignore_path 排除
+fn walk_dir(dir_path: &Path, hidden: bool) -> Result<impl Iterator<Item = PathBuf>> {
+    let pattern = format!("{}/**/*", dir_path.display());
+    let options = MatchOptions {
+        require_literal_leading_dot: !hidden,
+        ..MatchOptions::new()
+    };
+    Ok(glob_with(&pattern, options)?.flatten())
+}
+
+fn collect_files(dir_path: &Path, opts: &PackOptions, skipped: &mut SkipReport) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut extensionless_included = 0;
+    let mut extensionless_skipped = 0;
+    let mut long_line_skipped = 0;
+    let test_patterns = if opts.exclude_tests || opts.tests_only {
+        test_file_patterns(&opts.test_patterns)?
+    } else {
+        Vec::new()
+    };
+    let mut test_files_skipped = 0;
+    let mut binary_filtered = 0;
+    let excludes = glob_patterns(&opts.exclude)?;
+    let includes = glob_patterns(&opts.include)?;
+    let trxxignore = load_trxxignore(dir_path)?;
+    let gitignore = if opts.gitignore {
+        Some(GitignoreRules::load(dir_path)?)
+    } else {
+        None
+    };
+    let authored = match &opts.git_author {
+        Some(author) => Some(git_authored_files(dir_path, author)?),
+        None => None,
+    };
+    
+    for path in walk_dir(dir_path, opts.hidden)? {
+        if !path.is_file() {
+            continue;
+        }
+        let rel = path.strip_prefix(dir_path).unwrap_or(&path);
+        if should_ignore_path(rel, opts) {
+            skipped.add(SkipReason::Ignored, &path);
+            continue;
+        }
+        if opts.max_depth.is_some_and(|depth| rel.components().count() > depth + 1) {
+            skipped.add(SkipReason::TooDeep, &path);
+            continue;
+        }
+        if matches_path_or_parents(rel, &excludes) {
+            skipped.add(SkipReason::Excluded, &path);
+            continue;
+        }
+        if trxxignore.as_ref().is_some_and(|rules| rules.matched_path_or_any_parents(&path, false).is_ignore()) {
+            skipped.add(SkipReason::Trxxignore, &path);
+            continue;
+        }
+        if gitignore.as_ref().is_some_and(|rules| rules.is_ignored(&path, false)) {
+            skipped.add(SkipReason::Gitignore, &path);
+            continue;
+        }
+        // 先应用所有忽略规则，排除优先于包含
+        if !includes.is_empty() && !matches_path_or_parents(rel, &includes) {
+            skipped.add(SkipReason::NotIncluded, &path);
+            continue;
+        }
+        if authored.as_ref().is_some_and(|set| !set.contains(&path)) {
+            skipped.add(SkipReason::GitAuthor, &path);
+            continue;
+        }
+        if !test_patterns.is_empty() && is_test_file(rel, &test_patterns) == opts.exclude_tests {
+            test_files_skipped += 1;
+            skipped.add(SkipReason::TestFilter, &path);
+            continue;
+        }
+        let keep = should_process_file(&path, opts);
+        if path.extension().is_none() {
+            if keep {
+                extensionless_included += 1;
+            } else {
+                extensionless_skipped += 1;
+            }
+        }
+        if !keep {
+            skipped.add(unsupported_reason(&path, opts), &path);
+            continue;
+        }
+        if (opts.no_binary || opts.binary_only) && is_binary_file(&path, opts) == opts.no_binary {
+            binary_filtered += 1;
+            skipped.add(SkipReason::BinaryFilter, &path);
+            continue;
+        }
+        if let Some(limit) = opts.max_line_length {
+            if !is_binary_file(&path, opts) && has_line_longer_than(&path, limit) {
+                long_line_skipped += 1;
+                skipped.add(SkipReason::LongLine, &path);
+                continue;
+            }
+        }
+        files.push(path);
+    }
+
+    if opts.exclude_tests {
+        info!("排除了 {} 个测试文件", test_files_skipped);
+    } else if opts.tests_only {
+        info!("排除了 {} 个非测试文件", test_files_skipped);
+    }
+
+    if opts.no_binary {
+        info!("排除了 {} 个二进制文件", binary_filtered);
+    } else if opts.binary_only {
+        info!("排除了 {} 个文本文件", binary_filtered);
+    }
+
+    if opts.include_lockfiles {
+        let lockfiles = files.iter().filter(|p| p.to_string_lossy().ends_with(".lock")).count();
+        if lockfiles > 0 {
+            info!("包含 {} 个锁文件", lockfiles);
+        }
+    }
+
+    if long_line_skipped > 0 {
+        info!("因存在超长行跳过 {} 个文件", long_line_skipped);
+    }
+
+    if extensionless_included + extensionless_skipped > 0 {
+        info!("无扩展名文件: 包含 {} 个，跳过 {} 个", extensionless_included, extensionless_skipped);
+    }
+    
+    Ok(files)
+}
+
+// 收集空目录，同样应用忽略和排除规则，按路径排序
+fn collect_empty_dirs(dir_path: &Path, opts: &PackOptions) -> Result<Vec<PathBuf>> {
+    let excludes = glob_patterns(&opts.exclude)?;
+    let trxxignore = load_trxxignore(dir_path)?;
+    let gitignore = if opts.gitignore {
+        Some(GitignoreRules::load(dir_path)?)
+    } else {
+        None
+    };
+
+    let mut dirs = Vec::new();
+    for path in walk_dir(dir_path, opts.hidden)? {
+        if !path.is_dir() || fs::read_dir(&path).map_or(true, |mut entries| entries.next().is_some()) {
+            continue;
+        }
+        let rel = path.strip_prefix(dir_path).unwrap_or(&path);
+        let ignored = in_ignored_dir(rel)
+            || opts.max_depth.is_some_and(|depth| rel.components().count() > depth + 1)
+            || matches_path_or_parents(rel, &excludes)
+            || trxxignore.as_ref().is_some_and(|rules| rules.matched_path_or_any_parents(&path, true).is_ignore())
+            || gitignore.as_ref().is_some_and(|rules| rules.is_ignored(&path, true));
+        if !ignored {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn sort_files(files: &mut [PathBuf], root: &Path, order: SortOrder) {
+    let key = |path: &PathBuf| path.strip_prefix(root).unwrap_or(path).to_string_lossy().to_string();
+    match order {
+        SortOrder::Path => files.sort_by_key(key),
+        // 自然排序认为相等时再按字典序比较，保证结果稳定
+        SortOrder::Natural => files.sort_by_cached_key(|path| NaturalKey(key(path))),
+        // 大小或修改时间相同时按路径排序，保证结果稳定
+        SortOrder::Size => files.sort_by_cached_key(|path| {
+            (path.metadata().map(|m| m.len()).unwrap_or(0), key(path))
+        }),
+        SortOrder::Mtime => files.sort_by_cached_key(|path| {
+            (path.metadata().and_then(|m| m.modified()).ok(), key(path))
+        }),
+    }
+}
+
+// 按自然顺序比较的字符串
+#[derive(PartialEq, Eq)]
+struct NaturalKey(String);
+
+impl Ord for NaturalKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        natord::compare(&self.0, &other.0).then_with(|| self.0.cmp(&other.0))
+    }
+}
+
+impl PartialOrd for NaturalKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// 没有扩展名或扩展名没有意义的常见文件，按完整文件名识别语言
+const FILENAME_LANGUAGES: &[(&str, &str)] = &[
+    ("Dockerfile", "dockerfile"),
+    ("Containerfile", "dockerfile"),
+    ("Makefile", "makefile"),
+    ("makefile", "makefile"),
+    ("GNUmakefile", "makefile"),
+    ("CMakeLists.txt", "cmake"),
+    ("Rakefile", "ruby"),
+    ("Gemfile", "ruby"),
+    ("Vagrantfile", "ruby"),
+    ("Jenkinsfile", "groovy"),
+    ("justfile", "just"),
+    ("Justfile", "just"),
+    (".bashrc", "bash"),
+    (".bash_profile", "bash"),
+    (".profile", "bash"),
+    (".zshrc", "zsh"),
+    (".vimrc", "vim"),
+];
+
+fn filename_language(path: &Path) -> Option<&'static str> {
+    let file_name = path.file_name()?.to_str()?;
+    FILENAME_LANGUAGES.iter()
+        .find(|(name, _)| *name == file_name)
+        .map(|(_, lang)| *lang)
+}
+
+// 根据 shebang 中的解释器识别脚本语言，例如 `#!/usr/bin/env python3`
+fn shebang_language(content: &str) -> Option<&'static str> {
+    let line = content.lines().next()?.strip_prefix("#!")?;
+    let mut args = line.split_whitespace();
+    let mut program = args.next()?.rsplit('/').next()?;
+    // `env` 后面才是真正的解释器，跳过 -S 等参数
+    if program == "env" {
+        program = args.find(|arg| !arg.starts_with('-'))?;
+    }
+    // python3.11、ruby2.7 等带版本号的解释器按名称识别
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let lang = match name {
+        "python" => "python",
+        "node" | "nodejs" => "javascript",
+        "deno" | "ts-node" => "typescript",
+        "bash" | "sh" | "dash" | "ksh" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "php" => "php",
+        "lua" => "lua",
+        "pwsh" => "powershell",
+        "Rscript" => "r",
+        _ => return None,
+    };
+    Some(lang)
+}
+
+// 先按文件名查找，再根据扩展名映射表得到文件的语言
+fn file_language<'a>(path: &Path, extension_map: &'a HashMap<String, String>) -> Option<&'a str> {
+    if let Some(lang) = filename_language(path) {
+        return Some(lang);
+    }
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .and_then(|ext| extension_map.get(&ext))
+        .map(String::as_str)
+}
+
+// 只保留语言在 langs 中的文件，未识别语言的文件也被丢弃
+fn filter_languages(files: Vec<PathBuf>, extension_map: &HashMap<String, String>, langs: &[String], skipped: &mut SkipReport) -> Vec<PathBuf> {
+    let before = files.len();
+    let kept: Vec<PathBuf> = files.into_iter()
+        .filter(|path| {
+            let keep = file_language(path, extension_map)
+                .is_some_and(|lang| langs.iter().any(|l| l.eq_ignore_ascii_case(lang)));
+            if !keep {
+                skipped.add(SkipReason::LanguageFilter, path);
+            }
+            keep
+        })
+        .collect();
+    info!("只保留语言 {}，排除了 {} 个文件", langs.join(", "), before - kept.len());
+    kept
+}
+
+// 按当前顺序保留每种语言的前 limit 个文件，未识别语言的文件作为一组统计
+fn limit_per_language(files: Vec<PathBuf>, extension_map: &HashMap<String, String>, limit: usize, skipped: &mut SkipReport) -> Vec<PathBuf> {
+    let mut counts: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    let mut kept = Vec::with_capacity(files.len());
+    for path in files {
+        let lang = file_language(&path, extension_map).unwrap_or("未识别");
+        let (included, dropped) = counts.entry(lang).or_default();
+        if *included < limit {
+            *included += 1;
+            kept.push(path);
+        } else {
+            *dropped += 1;
+            skipped.add(SkipReason::LanguageLimit, &path);
+        }
+    }
+
+    for (lang, (_, dropped)) in counts {
+        if dropped > 0 {
+            info!("语言 {} 超出 {} 个文件的限制，丢弃 {} 个文件", lang, limit, dropped);
+        }
+    }
+    kept
+}
+
+fn is_readme(path: &Path) -> bool {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .map(|s| s.eq_ignore_ascii_case("readme"))
+        .unwrap_or(false)
+}
+
+// 只调整 README 的位置：每个目录第一次出现时先输出该目录下的 README，其余文件保持原有顺序
+fn readme_first(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut readmes: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for path in files.iter().filter(|p| is_readme(p)) {
+        let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        readmes.entry(parent).or_default().push(path.clone());
+    }
+
+    let mut ordered = Vec::with_capacity(files.len());
+    for path in files {
+        let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        if let Some(dir_readmes) = readmes.remove(&parent) {
+            ordered.extend(dir_readmes);
+        }
+        if !is_readme(&path) {
+            ordered.push(path);
+        }
+    }
+    ordered
+}
+
+// 常见语言的测试文件命名约定
+const TEST_FILE_PATTERNS: &[&str] = &[
+    "**/*_test.rs", "**/*_test.go", "**/*_test.py", "**/test_*.py",
+    "**/*.test.js", "**/*.test.jsx", "**/*.test.ts", "**/*.test.tsx",
+    "**/*.spec.js", "**/*.spec.jsx", "**/*.spec.ts", "**/*.spec.tsx",
+    "**/*_spec.rb", "**/*Test.java", "**/tests/**", "**/__tests__/**",
+];
+
+fn test_file_patterns(extra: &[String]) -> Result<Vec<glob::Pattern>> {
+    TEST_FILE_PATTERNS.iter()
+        .copied()
+        .chain(extra.iter().map(String::as_str))
+        .map(|p| glob::Pattern::new(p).with_context(|| format!("无效的测试文件模式 {}", p)))
+        .collect()
+}
+
+fn is_test_file(rel_path: &Path, patterns: &[glob::Pattern]) -> bool {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    let rel = rel_path.to_string_lossy().replace('\\', "/");
+    patterns.iter().any(|p| p.matches_with(&rel, options))
+}
+
+fn glob_patterns(patterns: &[String]) -> Result<Vec<glob::Pattern>> {
+    patterns.iter()
+        .map(|p| glob::Pattern::new(p).with_context(|| format!("无效的 glob 模式 {}", p)))
+        .collect()
+}
+
+// 相对路径本身或它所在的任意一级目录匹配即可，例如 `docs` 会匹配 docs 目录下的所有文件
+fn matches_path_or_parents(rel_path: &Path, patterns: &[glob::Pattern]) -> bool {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    rel_path.ancestors()
+        .filter(|p| !p.as_os_str().is_empty())
+        .any(|p| {
+            let rel = p.to_string_lossy().replace('\\', "/");
+            patterns.iter().any(|pattern| pattern.matches_with(&rel, options))
+        })
+}
+
+// 通过 git log 找出指定作者的提交修改过的文件；使用 -z 以 NUL 分隔，非 ASCII 文件名才不会被加上引号和转义
+fn git_authored_files(dir_path: &Path, author: &str) -> Result<std::collections::HashSet<PathBuf>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir_path)
+        .args(["log", "-z", "--name-only", "--relative", "--pretty=format:"])
+        .arg(format!("--author={}", author))
+        .output()
+        .context("无法执行 git，请确认已安装 git")?;
+    if !output.status.success() {
+        anyhow::bail!("git log 执行失败: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    let files: std::collections::HashSet<PathBuf> = output.stdout
+        .split(|&b| b == 0)
+        .map(|name| String::from_utf8_lossy(name).trim_start_matches('\n').to_string())
+        .filter(|name| !name.is_empty())
+        .map(|name| dir_path.join(name))
+        .collect();
+    info!("作者 {} 修改过 {} 个文件", author, files.len());
+    Ok(files)
+}
+
+// 打包目录下的 .trxxignore，语法与 .gitignore 相同，只对 trxx 生效；文件不存在时返回 None
+fn load_trxxignore(dir_path: &Path) -> Result<Option<Gitignore>> {
+    let path = dir_path.join(".trxxignore");
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let mut builder = GitignoreBuilder::new(dir_path);
+    if let Some(e) = builder.add(&path) {
+        warn!("无法解析 {}: {}", path.display(), e);
+    }
+    let rules = builder
+        .build()
+        .with_context(|| format!("无法解析 {}", path.display()))?;
+    Ok(Some(rules))
+}
+
+// 打包目录及其子目录中的所有 .gitignore 规则
+struct GitignoreRules {
+    // 按目录深度从深到浅排列，深层目录的规则优先
+    rules: Vec<(PathBuf, Gitignore)>,
+}
+
+impl GitignoreRules {
+    fn load(dir_path: &Path) -> Result<Self> {
+        let pattern = format!("{}/**/.gitignore", dir_path.display());
+        let mut rules = Vec::new();
+        for path in glob(&pattern)?.flatten() {
+            let Some(dir) = path.parent() else {
+                continue;
+            };
+            let mut builder = GitignoreBuilder::new(dir);
+            if let Some(e) = builder.add(&path) {
+                warn!("无法解析 {}: {}", path.display(), e);
+            }
+            let gitignore = builder
+                .build()
+                .with_context(|| format!("无法解析 {}", path.display()))?;
+            rules.push((dir.to_path_buf(), gitignore));
+        }
+        rules.sort_by_key(|(dir, _)| std::cmp::Reverse(dir.components().count()));
+        Ok(GitignoreRules { rules })
+    }
+
+    // 和 Git 一样，由最近一层 .gitignore 中最后匹配的规则决定，`!` 开头的规则可以重新包含文件
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for (dir, gitignore) in &self.rules {
+            if !path.starts_with(dir) {
+                continue;
+            }
+            let matched = gitignore.matched_path_or_any_parents(path, is_dir);
+            if matched.is_ignore() {
+                return true;
+            }
+            if matched.is_whitelist() {
+                return false;
+            }
+        }
+        false
+    }
+}
+
+// 文件头中的路径总是使用 / 分隔，在 Windows 上打包的文件也能在其他平台还原
+fn header_path(path: &Path, root: &Path, opts: &PackOptions) -> Result<String> {
+    let header = match opts.relpath_style {
+        RelpathStyle::Relative => path.strip_prefix(root)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        RelpathStyle::Basename => path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        // Unix 的文件名中可以有 \，只在 Windows 上转换
+        RelpathStyle::Absolute if cfg!(windows) => path.to_string_lossy().replace('\\', "/"),
+        RelpathStyle::Absolute => path.to_string_lossy().to_string(),
+    };
+    match &opts.root_prefix {
+        Some(prefix) if opts.relpath_style != RelpathStyle::Absolute => Ok(format!("{}/{}", prefix, header)),
+        _ => Ok(header),
+    }
+}
+
+/// 打包 dir 下的文件并按 opts.format 返回 Markdown 或 JSON 文本，不写入任何文件；没有可打包的文件时返回空字符串。
+/// tar 和 zip 的打包结果不是文本，需要通过 pack_files 写入文件
+pub fn pack(dir: &Path, opts: &PackOptions) -> Result<String> {
+    if matches!(opts.format, OutputFormat::Tar | OutputFormat::Zip) {
+        anyhow::bail!("pack 只能返回 Markdown 或 JSON 格式的文本，tar 和 zip 格式请使用 pack_files");
+    }
+    let extension_map = extension_map(opts)?;
+    let abs_path = fs::canonicalize(dir)
+        .with_context(|| format!("无法访问目录 {}", dir.display()))?;
+    let mut stats = PackStats::default();
+    let files = gather_files(&abs_path, &extension_map, opts, &mut stats)?;
+    if files.is_empty() {
+        return Ok(String::new());
+    }
+    match opts.format {
+        OutputFormat::Json => render_json(&files, &abs_path, &extension_map, opts, &mut stats),
+        _ => Ok(render_sections(&files, &abs_path, &extension_map, opts, &mut stats)?.concat()),
+    }
+}
+
+/// 将打包内容中的文件还原到 out_dir 下
+pub fn revert(content: &str, out_dir: &Path) -> Result<()> {
+    let opts = RevertOptions {
+        output_dir: Some(out_dir.to_path_buf()),
+        ..Default::default()
+    };
+    revert_archive(content, &opts)
+}
+
+pub fn pack_files(dir_path: &str, opts: &PackOptions) -> Result<()> {
+    let started = Instant::now();
+    let extension_map = extension_map(opts)?;
+    let abs_path = fs::canonicalize(dir_path)?;
+    let mut stats = PackStats::default();
+    
+    // 先收集所有符合条件的文件
+    let mut files = gather_files(&abs_path, &extension_map, opts, &mut stats)?;
+    if opts.interactive && !files.is_empty() {
+        files = pick_files(files, &abs_path)?;
+    }
+    
+    if files.is_empty() {
+        warn!("没有找到任何有效的文本文件");
+        if opts.skip_report || opts.list_skipped {
+            stats.skipped.print(&abs_path, opts.list_skipped);
+        } else {
+            stats.skipped.log_summary();
+        }
+        return Ok(());
+    }
+
+    if opts.estimate {
+        return print_estimate(&files, &abs_path, &extension_map, opts);
+    }
+
+    if opts.relpath_style == RelpathStyle::Absolute {
+        warn!("文件头将使用绝对路径，还原时需要加上 --allow-absolute 才会写回这些绝对位置，请只在可信环境中还原该文件");
+        if opts.root_prefix.is_some() {
+            warn!("文件头使用绝对路径时忽略 --root-prefix");
+        }
+    }
+
+    let sections = match opts.format {
+        OutputFormat::Markdown => render_sections(&files, &abs_path, &extension_map, opts, &mut stats)?,
+        OutputFormat::Json => vec![render_json(&files, &abs_path, &extension_map, opts, &mut stats)?],
+        OutputFormat::Tar | OutputFormat::Zip => Vec::new(),
+    };
+    // tar 和 zip 是二进制格式，其他格式的打包结果都是文本
+    let all_content = match opts.format {
+        OutputFormat::Tar => render_tar(&files, &abs_path, opts, &mut stats)?,
+        OutputFormat::Zip => render_zip(&files, &abs_path, opts, &mut stats)?,
+        _ => sections.concat().into_bytes(),
+    };
+
+    // 日志都在标准错误中，写到标准输出时不会混入打包结果
+    let destination = if opts.stdout {
+        let bytes = compress_output(&all_content, opts)?;
+        if opts.dry_run {
+            eprintln!("将写入 标准输出（{}）", format_size(bytes.len() as u64));
+        } else {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(&bytes)
+                .and_then(|_| stdout.flush())
+                .context("无法写入标准输出")?;
+        }
+        "标准输出".to_string()
+    } else if opts.split_size.is_some() || opts.split_tokens.is_some() {
+        let output_path = output_path(opts)?;
+        let chunks = split_chunks(sections, opts);
+        for (i, chunk) in chunks.iter().enumerate() {
+            write_output(&compressed_path(chunk_path(&output_path, i + 1), opts), chunk.as_bytes(), opts)?;
+        }
+        // 删除上次拆分时留下的多余分段，避免还原时混入旧内容
+        for n in (chunks.len() + 1..).take_while(|_| !opts.dry_run) {
+            let stale = compressed_path(chunk_path(&output_path, n), opts);
+            if fs::remove_file(&stale).is_err() {
+                break;
+            }
+        }
+        format!("{} 等 {} 个文件", compressed_path(chunk_path(&output_path, 1), opts).display(), chunks.len())
+    } else {
+        let output_path = compressed_path(output_path(opts)?, opts);
+        write_output(&output_path, &all_content, opts)?;
+        output_path.display().to_string()
+    };
+    let total_tokens = estimate_tokens(&String::from_utf8_lossy(&all_content));
+    if opts.dry_run {
+        eprintln!(
+            "试运行，没有写入任何文件：{} 将包含 {} 个文件（{}，约 {} tokens）",
+            destination,
+            stats.files,
+            format_size(stats.bytes),
+            format_count(total_tokens),
+        );
+    } else {
+        info!(
+            "文件已打包到 {}：共 {} 个文件（{}，约 {} tokens），耗时 {:.1}s",
+            destination,
+            stats.files,
+            format_size(stats.bytes),
+            format_count(total_tokens),
+            started.elapsed().as_secs_f64(),
+        );
+    }
+
+    if opts.tokens {
+        stats.print_tokens(total_tokens);
+    }
+    if let Some(limit) = opts.token_limit.filter(|&limit| total_tokens > limit) {
+        warn!("估算 token 数约 {}，超过了上限 {}", format_count(total_tokens), format_count(limit));
+    }
+
+    if opts.normalize_whitespace {
+        info!("已删除 {} 个文件中 {} 行的行尾空白", stats.whitespace_files, stats.whitespace_lines);
+    }
+
+    if opts.skip_report || opts.list_skipped {
+        stats.skipped.print(&abs_path, opts.list_skipped);
+    } else {
+        stats.skipped.log_summary();
+    }
+
+    if opts.suggest_ignores {
+        suggest_ignores(&files, &abs_path);
+    }
+
+    if opts.clipboard && !opts.dry_run {
+        if all_content.len() > CLIPBOARD_WARN_SIZE {
+            warn!("打包结果有 {}（约 {} tokens），粘贴到聊天窗口时可能被截断或拒绝", format_size(all_content.len() as u64), format_count(total_tokens));
+        }
+        match copy_to_system_clipboard(&all_content) {
+            Ok(()) => info!("打包结果已复制到剪贴板"),
+            Err(e) => warn!("无法复制到剪贴板: {:#}", e),
+        }
+    }
+
+    // 其他文件已经照常输出，最后仍以失败退出，避免遗漏的文件被忽视
+    if !stats.failed.is_empty() {
+        eprintln!("共有 {} 个文件处理失败，已跳过：", stats.failed.len());
+        for (path, e) in &stats.failed {
+            eprintln!("  {}: {:#}", path.strip_prefix(&abs_path).unwrap_or(path).display(), e);
+        }
+        anyhow::bail!("{} 个文件处理失败", stats.failed.len());
+    }
+    Ok(())
+}
+
+// 在终端中列出目录和文件供勾选，默认全部选中；不在交互式终端中时打包所有文件
+fn pick_files(files: Vec<PathBuf>, root: &Path) -> Result<Vec<PathBuf>> {
+    use std::io::IsTerminal;
+
+    // 选择界面画在标准错误上，--stdout 输出到管道时仍然可以选择
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        warn!("不在交互式终端中，忽略 --interactive，打包所有文件");
+        return Ok(files);
+    }
+
+    let items = picker_items(&files, root);
+    let labels = items.iter().map(|(rel, is_dir)| {
+        let depth = rel.components().count() - 1;
+        let name = rel.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        let label = format!("{}{}{}", "  ".repeat(depth), name, if *is_dir { "/" } else { "" });
+        (label, true)
+    });
+    let selected = dialoguer::MultiSelect::new()
+        .with_prompt("选择要打包的文件（空格切换，回车确认，Esc 取消；取消选择目录会排除其中的所有文件）")
+        .items_checked(labels)
+        .max_length(20)
+        .report(false)
+        .interact_opt()?;
+    let Some(selected) = selected else {
+        anyhow::bail!("已取消打包");
+    };
+
+    let selected: std::collections::HashSet<usize> = selected.into_iter().collect();
+    let unselected = items.into_iter()
+        .enumerate()
+        .filter(|(i, _)| !selected.contains(i))
+        .map(|(_, (rel, _))| rel)
+        .collect();
+    Ok(apply_selection(files, root, &unselected))
+}
+
+// 按目录树的顺序排列选择项，每个目录排在它的第一个文件之前
+fn picker_items(files: &[PathBuf], root: &Path) -> Vec<(PathBuf, bool)> {
+    let mut sorted: Vec<_> = files.iter().map(|path| path.strip_prefix(root).unwrap_or(path)).collect();
+    sorted.sort();
+    let mut seen_dirs = std::collections::HashSet::new();
+    let mut items = Vec::new();
+    for rel in sorted {
+        let mut dirs: Vec<_> = rel.ancestors().skip(1).filter(|dir| !dir.as_os_str().is_empty()).collect();
+        dirs.reverse();
+        for dir in dirs {
+            if seen_dirs.insert(dir) {
+                items.push((dir.to_path_buf(), true));
+            }
+        }
+        items.push((rel.to_path_buf(), false));
+    }
+    items
+}
+
+/// 去掉没有选中的文件，以及没有选中的目录中的所有文件；unselected 中是相对于 root 的路径
+pub fn apply_selection(files: Vec<PathBuf>, root: &Path, unselected: &std::collections::HashSet<PathBuf>) -> Vec<PathBuf> {
+    files.into_iter()
+        .filter(|path| {
+            let rel = path.strip_prefix(root).unwrap_or(path);
+            !rel.ancestors().any(|p| unselected.contains(p))
+        })
+        .collect()
+}
+
+// 读取 --files-from 指定的文件列表，每行一个相对于打包目录的路径，`-` 表示从标准输入读取
+fn read_file_list(list: &Path, root: &Path, opts: &PackOptions, skipped: &mut SkipReport) -> Result<Vec<PathBuf>> {
+    let content = if list == Path::new("-") {
+        let mut content = String::new();
+        std::io::stdin().read_to_string(&mut content).context("无法读取标准输入")?;
+        content
+    } else {
+        fs::read_to_string(list)
+            .with_context(|| format!("无法读取文件列表 {}", list.display()))?
+    };
+
+    let mut files = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let path = match fs::canonicalize(root.join(line)) {
+            Ok(path) if path.starts_with(root) && path.is_file() => path,
+            Ok(_) | Err(_) => {
+                // 不存在或不在打包目录中的路径，--keep-going 时只给出警告
+                if !opts.keep_going {
+                    anyhow::bail!("文件列表中的 {} 不存在或不在打包目录中", line);
+                }
+                warn!("跳过文件列表中不存在或不在打包目录中的 {}", line);
+                continue;
+            }
+        };
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+        if !should_process_file(&path, opts) {
+            warn!("跳过不支持的文件 {}", line);
+            skipped.add(unsupported_reason(&path, opts), &path);
+            continue;
+        }
+        files.push(path);
+    }
+    Ok(files)
+}
+
+// 按大小或 token 上限把段落分组，只在文件之间断开；单个文件超过上限时单独成为一组
+fn split_chunks(sections: Vec<String>, opts: &PackOptions) -> Vec<String> {
+    let measure = |text: &str| match opts.split_tokens {
+        Some(_) => estimate_tokens(text) as u64,
+        None => text.len() as u64,
+    };
+    let limit = opts.split_tokens.map(|n| n as u64).or(opts.split_size).unwrap_or(u64::MAX);
+
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_size = 0;
+    for section in sections {
+        let size = measure(&section);
+        if !current.is_empty() && current_size + size > limit {
+            chunks.push(std::mem::take(&mut current));
+            current_size = 0;
+        }
+        current.push_str(&section);
+        current_size += size;
+    }
+    if !current.is_empty() || chunks.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+// 第 n 个分段的路径，例如 all_content.md 的第一段是 all_content.001.md
+fn chunk_path(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{:03}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}.{:03}", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+// 压缩输出时文件名追加 .gz
+fn compressed_path(mut path: PathBuf, opts: &PackOptions) -> PathBuf {
+    if opts.compress == OutputCompression::Gzip {
+        path.as_mut_os_string().push(".gz");
+    }
+    path
+}
+
+fn compress_output(content: &[u8], opts: &PackOptions) -> Result<Vec<u8>> {
+    match opts.compress {
+        OutputCompression::None => Ok(content.to_vec()),
+        OutputCompression::Gzip => gzip(content),
+    }
+}
+
+// 判断文件名是否是 trxx 的输出文件，包括压缩后的 .gz 和拆分后的分段
+fn is_output_name(file_name: &str, output_name: &str) -> bool {
+    let name = file_name.strip_suffix(".gz").unwrap_or(file_name);
+    if name == output_name {
+        return true;
+    }
+    let (stem, number) = match output_name.rsplit_once('.') {
+        Some((stem, ext)) => (stem, name.strip_suffix(ext).and_then(|n| n.strip_suffix('.'))),
+        None => (output_name, Some(name)),
+    };
+    number
+        .and_then(|n| n.strip_prefix(stem))
+        .and_then(|n| n.strip_prefix('.'))
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// 先打包一次，之后监视目录，文件变化时自动重新打包，直到进程被中断
+pub fn watch(dir_path: &str, opts: &PackOptions) -> Result<()> {
+    let abs_path = fs::canonicalize(dir_path)?;
+    if let Err(e) = pack_files(dir_path, opts) {
+        warn!("打包失败: {:#}", e);
+    }
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("无法创建文件监视器")?;
+    watcher.watch(&abs_path, RecursiveMode::Recursive)
+        .with_context(|| format!("无法监视目录 {}", abs_path.display()))?;
+    info!("正在监视 {}，按 Ctrl+C 退出", abs_path.display());
+
+    // 与打包使用相同的忽略规则，输出文件本身的变化也会被忽略，不会循环触发
+    let excludes = glob_patterns(&opts.exclude)?;
+    let trxxignore = load_trxxignore(&abs_path)?;
+    let gitignore = if opts.gitignore {
+        Some(GitignoreRules::load(&abs_path)?)
+    } else {
+        None
+    };
+    let is_relevant = |path: &Path| {
+        let rel = path.strip_prefix(&abs_path).unwrap_or(path);
+        !(is_editor_temp(path)
+            || should_ignore_path(rel, opts)
+            || matches_path_or_parents(rel, &excludes)
+            || trxxignore.as_ref().is_some_and(|rules| rules.matched_path_or_any_parents(path, false).is_ignore())
+            || gitignore.as_ref().is_some_and(|rules| rules.is_ignored(path, false)))
+    };
+
+    while let Ok(event) = rx.recv() {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("监视目录时出错: {}", e);
+                continue;
+            }
+        };
+        if event.kind.is_access() || !event.paths.iter().any(|p| is_relevant(p)) {
+            continue;
+        }
+        // 合并短时间内连续发生的变化，例如编辑器保存时的多次写入
+        while rx.recv_timeout(Duration::from_millis(300)).is_ok() {}
+
+        info!("{} 检测到文件变化，重新打包", humantime::format_rfc3339_seconds(std::time::SystemTime::now()));
+        if let Err(e) = pack_files(dir_path, opts) {
+            warn!("打包失败: {:#}", e);
+        }
+    }
+    Ok(())
+}
+
+// 编辑器保存文件时产生的临时文件
+fn is_editor_temp(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    name.ends_with('~')
+        || name.starts_with(".#")
+        || name.ends_with(".swp")
+        || name.ends_with(".swx")
+        || name.ends_with(".tmp")
+        // vim 保存前用来检查目录是否可写的文件
+        || name == "4913"
+}
+
+#[derive(Default, Serialize)]
+struct RepoStats {
+    files: usize,
+    bytes: u64,
+    binary_files: usize,
+    skipped_by_size: usize,
+    languages: BTreeMap<String, LanguageStats>,
+}
+
+#[derive(Default, Serialize)]
+struct LanguageStats {
+    files: usize,
+    bytes: u64,
+}
+
+/// 按与打包相同的规则选择文件，统计文件数、大小和各语言的占比，不写入打包文件
+pub fn print_stats(dir_path: &str, opts: &PackOptions, json: bool) -> Result<()> {
+    let extension_map = extension_map(opts)?;
+    let abs_path = fs::canonicalize(dir_path)?;
+    let mut pack_stats = PackStats::default();
+    let files = gather_files(&abs_path, &extension_map, opts, &mut pack_stats)?;
+
+    let mut stats = RepoStats {
+        skipped_by_size: pack_stats.skipped.0.get(&SkipReason::Size).map_or(0, Vec::len),
+        ..Default::default()
+    };
+    for path in &files {
+        let size = path.metadata().map(|m| m.len()).unwrap_or(0);
+        let lang = if is_binary_file(path, opts) {
+            stats.binary_files += 1;
+            "binary"
+        } else {
+            file_language(path, &extension_map).unwrap_or("-")
+        };
+        stats.files += 1;
+        stats.bytes += size;
+        let entry = stats.languages.entry(lang.to_string()).or_default();
+        entry.files += 1;
+        entry.bytes += size;
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+
+    // 按大小从大到小排列
+    let mut rows: Vec<_> = stats.languages.iter().collect();
+    rows.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(b.0)));
+    let lang_width = rows.iter().map(|(lang, _)| lang.chars().count()).max().unwrap_or(0);
+    for (lang, entry) in rows {
+        let share = if stats.bytes == 0 { 0.0 } else { entry.bytes as f64 * 100.0 / stats.bytes as f64 };
+        println!("{:<width$}  {:>6} 个文件  {:>10}  {:>5.1}%", lang, entry.files, format_size(entry.bytes), share, width = lang_width);
+    }
+    println!(
+        "共 {} 个文件，{}；其中二进制文件 {} 个，因大小限制跳过 {} 个",
+        stats.files,
+        format_size(stats.bytes),
+        stats.binary_files,
+        stats.skipped_by_size,
+    );
+    Ok(())
+}
+
+/// 列出会被打包的文件及其大小和语言，不读取文件内容，也不生成打包文件
+pub fn list_files(dir_path: &str, opts: &PackOptions) -> Result<()> {
+    let extension_map = extension_map(opts)?;
+    let abs_path = fs::canonicalize(dir_path)?;
+    let mut stats = PackStats::default();
+    let files = gather_files(&abs_path, &extension_map, opts, &mut stats)?;
+
+    let mut rows = Vec::with_capacity(files.len());
+    let mut total_size = 0;
+    for path in &files {
+        let rel_path = header_path(path, &abs_path, opts)?;
+        let size = path.metadata().map(|m| m.len()).unwrap_or(0);
+        let lang = if is_binary_file(path, opts) {
+            "binary"
+        } else {
+            file_language(path, &extension_map).unwrap_or("-")
+        };
+        total_size += size;
+        rows.push((rel_path, size, lang));
+    }
+
+    let path_width = rows.iter().map(|(path, _, _)| path.chars().count()).max().unwrap_or(0);
+    for (path, size, lang) in &rows {
+        println!("{:<width$}  {:>10}  {}", path, format_size(*size), lang, width = path_width);
+    }
+    println!("共 {} 个文件，{}", rows.len(), format_size(total_size));
+
+    if opts.skip_report || opts.list_skipped {
+        stats.skipped.print(&abs_path, opts.list_skipped);
+    }
+    Ok(())
+}
+
+// 收集、排序并筛选要打包的文件
+fn gather_files(abs_path: &Path, extension_map: &HashMap<String, String>, opts: &PackOptions, stats: &mut PackStats) -> Result<Vec<PathBuf>> {
+    // 指定了文件列表时不遍历目录，保持列表中的顺序
+    if let Some(list) = &opts.files_from {
+        let mut files = read_file_list(list, abs_path, opts, &mut stats.skipped)?;
+        if !opts.lang_only.is_empty() {
+            files = filter_languages(files, extension_map, &opts.lang_only, &mut stats.skipped);
+        }
+        return Ok(match opts.limit_per_language {
+            Some(limit) => limit_per_language(files, extension_map, limit, &mut stats.skipped),
+            None => files,
+        });
+    }
+
+    let mut files = info_span!("collect").in_scope(|| collect_files(abs_path, opts, &mut stats.skipped))?;
+    // 遍历目录的顺序因平台而异，总是排序才能保证多次打包的结果一致
+    sort_files(&mut files, abs_path, opts.sort);
+    if opts.readme_first {
+        files = readme_first(files);
+    }
+    if let Some(since) = &opts.since {
+        files = changed_since(files, abs_path, opts, since)?;
+    }
+    if !opts.lang_only.is_empty() {
+        files = filter_languages(files, extension_map, &opts.lang_only, &mut stats.skipped);
+    }
+    if let Some(limit) = opts.limit_per_language {
+        files = limit_per_language(files, extension_map, limit, &mut stats.skipped);
+    }
+    Ok(files)
+}
+
+// 与之前的打包文件比较，只保留新增或有变化的文件
+fn changed_since(files: Vec<PathBuf>, root: &Path, opts: &PackOptions, since: &Path) -> Result<Vec<PathBuf>> {
+    let content = read_archives(&[since.to_string_lossy().into_owned()])?;
+    let mut previous = HashMap::new();
+    for_each_file(&content, &opts.header_prefix, |file| {
+        previous.insert(file.path.clone(), file);
+        Ok(())
+    })?;
+
+    // 之前的打包文件本身可能就在打包目录中
+    let since_path = fs::canonicalize(since).ok();
+    let (mut added, mut changed) = (0, 0);
+    let mut kept = Vec::new();
+    for path in files {
+        if since_path.as_ref() == Some(&path) {
+            continue;
+        }
+        match previous.remove(&header_path(&path, root, opts)?) {
+            None => added += 1,
+            Some(file) if file_changed(&path, &file) => changed += 1,
+            Some(_) => continue,
+        }
+        kept.push(path);
+    }
+    // 增量打包文件无法表示删除，只给出提示
+    info!(
+        "与 {} 相比：新增 {} 个文件，修改 {} 个文件，删除 {} 个文件",
+        since.display(), added, changed, previous.len(),
+    );
+    Ok(kept)
+}
+
+// 大小和修改时间都与记录的一致时认为没有变化（需要之前打包时使用 --metadata），否则比较还原出的内容
+fn file_changed(path: &Path, previous: &ArchiveFile) -> bool {
+    if let Ok(metadata) = path.metadata() {
+        let size = metadata.len().to_string();
+        let mtime = metadata.modified().ok().map(|m| humantime::format_rfc3339_seconds(m).to_string());
+        if previous.comment_value("size") == Some(size.as_str())
+            && mtime.is_some()
+            && previous.comment_value("mtime") == mtime.as_deref()
+        {
+            return false;
+        }
+    }
+    fs::read(path).map_or(true, |bytes| bytes != previous.bytes)
+}
+
+// 生成打包内容：第一项是可选的元信息和文件清单，之后每一项是一个文件的段落
+fn render_sections(files: &[PathBuf], abs_path: &Path, extension_map: &HashMap<String, String>, opts: &PackOptions, stats: &mut PackStats) -> Result<Vec<String>> {
+    let mut all_content = String::new();
+    if opts.header {
+        all_content.push_str(&archive_header(abs_path, opts));
+    }
+
+    // 处理每个文件
+    let process_span = info_span!("process", files = files.len());
+    let mut seen_headers = std::collections::HashSet::new();
+    let mut rel_paths = Vec::with_capacity(files.len());
+    for path in files {
+        let rel_path = header_path(path, abs_path, opts)?;
+        if !seen_headers.insert(rel_path.clone()) {
+            warn!("文件头路径 {} 重复，还原时后面的文件会覆盖前面的", rel_path);
+        }
+        rel_paths.push(rel_path);
+    }
+
+    // 并行读取和处理文件，collect 会保持原来的顺序，结果与逐个处理完全一致
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(opts.jobs.unwrap_or(0))
+        .build()
+        .context("无法创建线程池")?;
+
+    // 内容重复的文件对应的原文件路径，原文件总是排在前面
+    let same_as = if opts.dedup {
+        duplicate_files(files, &rel_paths, extension_map, opts, &pool)
+    } else {
+        vec![None; files.len()]
+    };
+
+    // 进度条画在标准错误上，不影响 --stdout 的输出
+    let progress = if opts.progress {
+        let bar = ProgressBar::new(files.len() as u64);
+        bar.set_style(ProgressStyle::with_template("{bar:40} {pos}/{len} {wide_msg}")?);
+        bar
+    } else {
+        ProgressBar::hidden()
+    };
+    let results = pool.install(|| {
+        files.par_iter().zip(&rel_paths).zip(&same_as).map(|((path, rel_path), same_as)| {
+            process_span.in_scope(|| {
+                let path = path.as_path();
+                let mut file_stats = PackStats::default();
+
+                // 检查是否是 markdown 文件
+                let is_markdown = path.extension()
+                    .and_then(|e| e.to_str())
+                    .map(|e| e.to_lowercase())
+                    .map(|ext| ext == "md")
+                    .unwrap_or(false);
+
+                // 读取并处理文件内容
+                debug!("打包文件: {}", rel_path);
+                progress.set_message(rel_path.clone());
+                let processed = match same_as {
+                    Some(original) => Ok(section_header(&opts.header_prefix, rel_path, &[format!("same-as={}", original)])),
+                    None => process_file(path, rel_path, extension_map, is_markdown, opts, &mut file_stats),
+                };
+                let content = match processed {
+                    Ok(content) => content,
+                    Err(e) if opts.keep_going => {
+                        warn!("跳过处理失败的文件 {}: {:#}", rel_path, e);
+                        file_stats.failed.push((path.to_path_buf(), e));
+                        String::new()
+                    }
+                    Err(e) => return Err(e),
+                };
+                progress.inc(1);
+                if !content.is_empty() {
+                    file_stats.files += 1;
+                    file_stats.bytes += path.metadata().map(|m| m.len()).unwrap_or(0);
+                    if opts.tokens {
+                        let lang = file_language(path, extension_map).unwrap_or("未识别");
+                        file_stats.tokens.insert(lang.to_string(), estimate_tokens(&content));
+                    }
+                }
+                Ok((content, file_stats))
+            })
+        }).collect::<Result<Vec<_>>>()
+    })?;
+
+    if opts.manifest {
+        let entries: Vec<_> = results.iter()
+            .zip(files.iter().zip(&rel_paths))
+            .filter(|((content, _), _)| !content.is_empty())
+            .map(|(_, (path, rel_path))| {
+                let size = path.metadata().map(|m| m.len()).unwrap_or(0);
+                let lang = file_language(path, extension_map).unwrap_or("-");
+                (rel_path.as_str(), size, lang)
+            })
+            .collect();
+        all_content.push_str(&render_manifest(&entries, &opts.header_prefix));
+    }
+
+    progress.finish_and_clear();
+
+    let mut sections = Vec::with_capacity(results.len() + 1);
+    sections.push(all_content);
+    for (content, file_stats) in results {
+        stats.merge(file_stats);
+        if !content.is_empty() {
+            sections.push(content);
+        }
+    }
+
+    // 空目录只有一行标记，没有正文
+    if opts.keep_empty_dirs {
+        for dir in collect_empty_dirs(abs_path, opts)? {
+            sections.push(format!("{}{}/\n\n", marker(&opts.header_prefix, "dir"), header_path(&dir, abs_path, opts)?));
+        }
+    }
+
+    Ok(sections)
+}
+
+// JSON 格式打包文件中的一个文件，二进制文件的内容使用 base64 编码
+#[derive(Serialize, Deserialize)]
+struct JsonFile {
+    path: String,
+    language: Option<String>,
+    binary: bool,
+    content: String,
+    // 文件权限，只有从 tar 归档或 zip 压缩包读取时才有
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mode: Option<u32>,
+}
+
+// JSON 格式的打包文件通常是数组，也接受只有一个文件的对象
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonArchive {
+    Files(Vec<JsonFile>),
+    File(JsonFile),
+}
+
+impl JsonArchive {
+    fn into_files(self) -> Vec<JsonFile> {
+        match self {
+            JsonArchive::Files(files) => files,
+            JsonArchive::File(file) => vec![file],
+        }
+    }
+}
+
+// 以 JSON 数组输出打包结果；文件内容保持原样，不做 Markdown 转义、换行转换等处理
+fn render_json(files: &[PathBuf], abs_path: &Path, extension_map: &HashMap<String, String>, opts: &PackOptions, stats: &mut PackStats) -> Result<String> {
+    let mut entries = Vec::with_capacity(files.len());
+    for path in files {
+        let rel_path = header_path(path, abs_path, opts)?;
+        debug!("打包文件: {}", rel_path);
+        let bytes = match fs::read(path).with_context(|| format!("无法读取文件 {}", path.display())) {
+            Ok(bytes) => bytes,
+            Err(e) if opts.keep_going => {
+                warn!("跳过处理失败的文件 {}: {:#}", rel_path, e);
+                stats.failed.push((path.clone(), e));
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        stats.files += 1;
+        stats.bytes += bytes.len() as u64;
+
+        let (binary, content) = match String::from_utf8(bytes) {
+            Ok(text) if !is_binary_file(path, opts) => (false, text),
+            Ok(text) => (true, base64::encode(text)),
+            Err(e) => (true, base64::encode(e.as_bytes())),
+        };
+        let language = file_language(path, extension_map)
+            .or_else(|| if binary { None } else { shebang_language(&content) })
+            .map(str::to_string);
+        if opts.tokens {
+            let lang = language.as_deref().unwrap_or("未识别").to_string();
+            *stats.tokens.entry(lang).or_default() += estimate_tokens(&content);
+        }
+        entries.push(JsonFile { path: rel_path, language, binary, content, mode: None });
+    }
+    let mut json = serde_json::to_string_pretty(&entries)?;
+    json.push('\n');
+    Ok(json)
+}
+
+// 以 tar 归档输出打包结果，保留文件的原始字节、权限和修改时间
+fn render_tar(files: &[PathBuf], abs_path: &Path, opts: &PackOptions, stats: &mut PackStats) -> Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(Vec::new());
+    if opts.deterministic {
+        builder.mode(tar::HeaderMode::Deterministic);
+    }
+    for path in files {
+        let rel_path = header_path(path, abs_path, opts)?;
+        debug!("打包文件: {}", rel_path);
+        let appended = builder.append_path_with_name(path, &rel_path)
+            .with_context(|| format!("无法将文件 {} 写入 tar 归档", path.display()));
+        match appended {
+            Ok(()) => {
+                stats.files += 1;
+                stats.bytes += path.metadata().map(|m| m.len()).unwrap_or(0);
+            }
+            Err(e) if opts.keep_going => {
+                warn!("跳过处理失败的文件 {}: {:#}", rel_path, e);
+                stats.failed.push((path.clone(), e));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if opts.keep_empty_dirs {
+        for dir in collect_empty_dirs(abs_path, opts)? {
+            builder.append_dir(header_path(&dir, abs_path, opts)?, &dir)?;
+        }
+    }
+    Ok(builder.into_inner()?)
+}
+
+// 把 tar 归档转换成 JSON 格式的打包内容，还原、预览和校验都可以沿用同一套流程
+fn tar_to_json(bytes: &[u8]) -> Result<String> {
+    let mut files = Vec::new();
+    for entry in tar::Archive::new(bytes).entries()? {
+        let mut entry = entry?;
+        // 目录会在还原文件时自动创建，链接等特殊文件不支持还原
+        if entry.header().entry_type() != tar::EntryType::Regular {
+            continue;
+        }
+        let path = entry.path()?.to_string_lossy().into_owned();
+        let mode = entry.header().mode().ok();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        files.push(JsonFile {
+            path,
+            language: None,
+            binary: true,
+            content: base64::encode(content),
+            mode,
+        });
+    }
+    Ok(serde_json::to_string(&files)?)
+}
+
+// tar 归档在偏移 257 处有 ustar 标记
+fn is_tar(bytes: &[u8]) -> bool {
+    bytes.get(257..262) == Some(b"ustar")
+}
+
+// 以 zip 压缩包输出打包结果，保存相对路径、权限和修改时间
+fn render_zip(files: &[PathBuf], abs_path: &Path, opts: &PackOptions, stats: &mut PackStats) -> Result<Vec<u8>> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let base_options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    for path in files {
+        let rel_path = header_path(path, abs_path, opts)?;
+        debug!("打包文件: {}", rel_path);
+        let appended = (|| -> Result<u64> {
+            let mut file = File::open(path)?;
+            let metadata = file.metadata()?;
+            let mut options = base_options;
+            if !opts.deterministic {
+                if let Some(time) = metadata.modified().ok().and_then(zip_time) {
+                    options = options.last_modified_time(time);
+                }
+            }
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                options = options.unix_permissions(metadata.permissions().mode());
+            }
+            writer.start_file(rel_path.as_str(), options)?;
+            std::io::copy(&mut file, &mut writer)?;
+            Ok(metadata.len())
+        })()
+        .with_context(|| format!("无法将文件 {} 写入 zip 压缩包", path.display()));
+        match appended {
+            Ok(size) => {
+                stats.files += 1;
+                stats.bytes += size;
+            }
+            Err(e) if opts.keep_going => {
+                warn!("跳过处理失败的文件 {}: {:#}", rel_path, e);
+                stats.failed.push((path.clone(), e));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if opts.keep_empty_dirs {
+        for dir in collect_empty_dirs(abs_path, opts)? {
+            writer.add_directory(header_path(&dir, abs_path, opts)?, base_options)?;
+        }
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
+// zip 只能记录 1980 到 2107 年之间、精确到 2 秒的时间，超出范围时不记录
+fn zip_time(time: std::time::SystemTime) -> Option<zip::DateTime> {
+    let text = humantime::format_rfc3339_seconds(time).to_string();
+    let field = |range: std::ops::Range<usize>| text.get(range)?.parse::<u16>().ok();
+    zip::DateTime::from_date_and_time(
+        field(0..4)?,
+        field(5..7)? as u8,
+        field(8..10)? as u8,
+        field(11..13)? as u8,
+        field(14..16)? as u8,
+        field(17..19)? as u8,
+    ).ok()
+}
+
+// 把 zip 压缩包转换成 JSON 格式的打包内容，与 tar 归档的处理方式相同
+fn zip_to_json(bytes: &[u8]) -> Result<String> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))?;
+    let mut files = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if !entry.is_file() {
+            continue;
+        }
+        let path = entry.name()?.into_owned();
+        // 在 Windows 上创建的压缩包没有 Unix 权限
+        let mode = entry.unix_mode().map(|mode| mode & 0o7777);
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        files.push(JsonFile {
+            path,
+            language: None,
+            binary: true,
+            content: base64::encode(content),
+            mode,
+        });
+    }
+    Ok(serde_json::to_string(&files)?)
+}
+
+// zip 压缩包以本地文件头开头，空压缩包只有目录结束记录
+fn is_zip(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06")
+}
+
+// 找出内容与前面某个文件完全相同的文件，返回每个文件对应的原文件路径；无法读取的文件不参与去重。
+// 删除注释时打包的内容取决于语言，引用只能还原出原文件处理后的内容，所以只在相同语言的文件之间去重
+fn duplicate_files(files: &[PathBuf], rel_paths: &[String], extension_map: &HashMap<String, String>, opts: &PackOptions, pool: &rayon::ThreadPool) -> Vec<Option<String>> {
+    let hashes: Vec<_> = pool.install(|| files.par_iter().map(|path| {
+        let lang = opts.strip_comments.then(|| file_language(path, extension_map)).flatten();
+        hash_file(path).ok().map(|hash| (hash, lang))
+    }).collect());
+    let mut first_seen = HashMap::new();
+    hashes.into_iter().enumerate().map(|(i, hash)| {
+        let original = *first_seen.entry(hash?).or_insert(i);
+        (original != i).then(|| rel_paths[original].clone())
+    }).collect()
+}
+
+// 复制到剪贴板的内容超过这个大小时提醒
+const CLIPBOARD_WARN_SIZE: usize = 1024 * 1024;
+
+/// 接收打包结果的剪贴板，测试中可以换成内存中的实现
+#[cfg(feature = "clipboard")]
+pub trait Clipboard {
+    fn set_text(&mut self, text: &str) -> Result<()>;
+}
+
+/// 系统剪贴板
+#[cfg(feature = "clipboard")]
+pub struct SystemClipboard;
+
+#[cfg(feature = "clipboard")]
+impl Clipboard for SystemClipboard {
+    fn set_text(&mut self, text: &str) -> Result<()> {
+        let mut clipboard = arboard::Clipboard::new().context("无法访问系统剪贴板")?;
+        clipboard.set_text(text).context("无法写入系统剪贴板")?;
+        Ok(())
+    }
+}
+
+/// 把打包结果写入剪贴板；tar 和 zip 格式的打包结果不是文本，返回错误
+#[cfg(feature = "clipboard")]
+pub fn copy_to_clipboard(clipboard: &mut impl Clipboard, content: &[u8]) -> Result<()> {
+    let text = std::str::from_utf8(content).context("tar 和 zip 格式的打包结果不是文本")?;
+    clipboard.set_text(text)
+}
+
+#[cfg(feature = "clipboard")]
+fn copy_to_system_clipboard(content: &[u8]) -> Result<()> {
+    copy_to_clipboard(&mut SystemClipboard, content)
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn copy_to_system_clipboard(_content: &[u8]) -> Result<()> {
+    anyhow::bail!("当前构建未启用 clipboard 功能，请使用 `cargo install trxx --features clipboard` 重新安装")
+}
+
+// 粗略估算 token 数：英文等 ASCII 文本约 4 个字符一个 token，中文等非 ASCII 字符约一个字符一个 token
+fn estimate_tokens(text: &str) -> usize {
+    let (ascii, other) = text.chars().fold((0usize, 0usize), |(ascii, other), c| {
+        if c.is_ascii() { (ascii + 1, other) } else { (ascii, other + 1) }
+    });
+    ascii.div_ceil(4) + other
+}
+
+// 较大的数字用 k 表示，例如 38.2k
+fn format_count(count: usize) -> String {
+    if count < 1000 {
+        count.to_string()
+    } else {
+        format!("{:.1}k", count as f64 / 1000.0)
+    }
+}
+
+// 默认的文本文件大小上限
+const DEFAULT_MAX_SIZE: u64 = 1024 * 1024;
+
+/// 解析文件大小，支持 K、M、G 后缀（按 1024 计算），例如 `512K`、`2M`
+pub fn parse_size(value: &str) -> std::result::Result<u64, String> {
+    let value = value.trim();
+    let upper = value.to_ascii_uppercase();
+    let number = upper.strip_suffix('B').unwrap_or(&upper);
+    let (number, unit) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 1024),
+        Some('M') => (&number[..number.len() - 1], 1024 * 1024),
+        Some('G') => (&number[..number.len() - 1], 1024 * 1024 * 1024),
+        _ => (number, 1),
+    };
+    number.trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(|| format!("无效的文件大小 {}，示例：1048576、512K、2M", value))
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+// --estimate：读取文件内容统计信息并输出报告，不生成打包文件
+fn print_estimate(files: &[PathBuf], root: &Path, extension_map: &HashMap<String, String>, opts: &PackOptions) -> Result<()> {
+    let mut total_size = 0u64;
+    let mut total_tokens = 0usize;
+    // 语言 -> (文件数, 字节数, token 数)
+    let mut by_language: BTreeMap<&str, (usize, u64, usize)> = BTreeMap::new();
+    let mut sizes = Vec::with_capacity(files.len());
+
+    for path in files {
+        let bytes = fs::read(path).with_context(|| format!("无法读取文件 {}", path.display()))?;
+        let size = bytes.len() as u64;
+        let tokens = if is_binary_file(path, opts) {
+            estimate_tokens(&base64::encode(&bytes))
+        } else {
+            estimate_tokens(&String::from_utf8_lossy(&bytes))
+        };
+        let lang = file_language(path, extension_map).unwrap_or("未识别");
+        let entry = by_language.entry(lang).or_default();
+        entry.0 += 1;
+        entry.1 += size;
+        entry.2 += tokens;
+        total_size += size;
+        total_tokens += tokens;
+        sizes.push((size, path.strip_prefix(root).unwrap_or(path).display().to_string()));
+    }
+
+    println!("文件数: {}", files.len());
+    println!("总大小: {}", format_size(total_size));
+    println!("估算 token 数: ~{}", total_tokens);
+    println!("按语言:");
+    for (lang, (count, size, tokens)) in &by_language {
+        println!("  {:<16} {:>6} 个文件  {:>10}  ~{} tokens", lang, count, format_size(*size), tokens);
+    }
+    println!("最大的文件:");
+    sizes.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    for (size, path) in sizes.iter().take(10) {
+        println!("  {:>10}  {}", format_size(*size), path);
+    }
+    Ok(())
+}
+
+// 只做分析和提示，不修改任何忽略配置
+fn suggest_ignores(files: &[PathBuf], root: &Path) {
+    const LOCK_FILES: &[&str] = &[
+        "package-lock.json", "pnpm-lock.yaml", "yarn.lock", "Cargo.lock",
+        "composer.lock", "poetry.lock", "Gemfile.lock", "go.sum",
+    ];
+    const GENERATED_DIRS: &[&str] = &["dist", "build", "out", "vendor", "coverage", "__pycache__"];
+    const LARGE_FILE: u64 = 256 * 1024;
+
+    let mut suggestions: BTreeMap<&str, std::collections::BTreeSet<String>> = BTreeMap::new();
+    for path in files {
+        let Ok(rel) = path.strip_prefix(root) else {
+            continue;
+        };
+        let rel_str = rel.to_string_lossy().replace('\\', "/");
+        let file_name = rel.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let dirs: Vec<&str> = rel.parent()
+            .map(|p| p.iter().filter_map(|c| c.to_str()).collect())
+            .unwrap_or_default();
+
+        if LOCK_FILES.contains(&file_name) {
+            suggestions.entry("依赖锁文件").or_default().insert(file_name.to_string());
+        }
+        if file_name.ends_with(".min.js") || file_name.ends_with(".min.css") {
+            let ext = if file_name.ends_with(".js") { "js" } else { "css" };
+            suggestions.entry("压缩后的产物").or_default().insert(format!("*.min.{}", ext));
+        }
+        if let Some(dir) = dirs.iter().find(|d| GENERATED_DIRS.contains(d)) {
+            suggestions.entry("构建或依赖目录").or_default().insert(format!("{}/", dir));
+        }
+        if let Some(dir) = dirs.iter().find(|d| d.starts_with('.')) {
+            suggestions.entry("隐藏目录").or_default().insert(format!("{}/", dir));
+        } else if file_name.starts_with('.') {
+            suggestions.entry("隐藏文件").or_default().insert(rel_str.clone());
+        }
+        if path.metadata().map(|m| m.len() > LARGE_FILE).unwrap_or(false) {
+            suggestions.entry("大文件（超过 256KB）").or_default().insert(rel_str);
+        }
+    }
+
+    if suggestions.is_empty() {
+        eprintln!("没有发现明显需要忽略的文件");
+        return;
+    }
+    eprintln!("以下文件可能需要忽略，可以考虑添加这些模式:");
+    for (category, patterns) in suggestions {
+        eprintln!("  {}:", category);
+        for pattern in patterns {
+            eprintln!("    {}", pattern);
+        }
+    }
+}
+
+// 打包文件开头的元信息，位于第一个文件头之前，还原时会被忽略
+fn archive_header(root: &Path, opts: &PackOptions) -> String {
+    let mut fields = Vec::new();
+    if !opts.deterministic {
+        fields.push(("generated", humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string()));
+    }
+
+    // 不是 git 仓库时静默跳过 git 信息
+    if let Some(commit) = git_output(root, &["rev-parse", "HEAD"]) {
+        fields.push(("git-commit", commit));
+        if !opts.deterministic {
+            if let Some(branch) = git_output(root, &["rev-parse", "--abbrev-ref", "HEAD"]) {
+                fields.push(("git-branch", branch));
+            }
+            if let Some(status) = git_output(root, &["status", "--porcelain"]) {
+                fields.push(("git-dirty", (!status.is_empty()).to_string()));
+            }
+        }
+    }
+
+    let mut header = String::from("<!-- trxx-header\n");
+    for (key, value) in fields {
+        header.push_str(&format!("{}: {}\n", key, value));
+    }
+    header.push_str("-->\n\n");
+    header
+}
+
+// 文件清单：每个文件的路径、字节数和语言，以及总计；还原时整段跳过
+fn render_manifest(entries: &[(&str, u64, &str)], prefix: &str) -> String {
+    let path_width = entries.iter().map(|(path, _, _)| path.chars().count()).max().unwrap_or(0);
+    let mut body = String::new();
+    let mut total = 0;
+    for (path, size, lang) in entries {
+        body.push_str(&format!("{:<width$}  {:>10}  {}\n", path, size, lang, width = path_width));
+        total += size;
+    }
+    body.push_str(&format!("共 {} 个文件，{} 字节\n", entries.len(), total));
+
+    let fence = "`".repeat(longest_backtick_run(&body).max(2) + 1);
+    format!("{}\n\n{}text\n{}{}\n\n", marker(prefix, "manifest"), fence, body, fence)
+}
+
+// 执行 git 命令并返回去掉首尾空白的输出，失败时返回 None
+fn git_output(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// 输出文件的路径，父目录在写入时才创建；--output-repo-root 时默认输出到仓库根目录下的 .trxx/，
+// 同时指定的相对 -o 路径相对于仓库根目录
+fn output_path(opts: &PackOptions) -> Result<PathBuf> {
+    let path = opts.output.clone().unwrap_or_else(|| PathBuf::from(opts.default_output()));
+    if !opts.output_repo_root || path.is_absolute() {
+        return Ok(path);
+    }
+
+    let cwd = std::env::current_dir()?;
+    match find_repo_root(&cwd) {
+        Some(root) if opts.output.is_some() => Ok(root.join(path)),
+        Some(root) => Ok(root.join(".trxx").join(path)),
+        None => {
+            warn!("当前目录不在 git 仓库中，输出到当前目录");
+            Ok(path)
+        }
+    }
+}
+
+// 写入一个输出文件，父目录不存在时自动创建；试运行时只输出将要写入的路径和大小
+fn write_output(path: &Path, content: &[u8], opts: &PackOptions) -> Result<()> {
+    let bytes = compress_output(content, opts)?;
+    if opts.dry_run {
+        eprintln!("将写入 {}（{}）", path.display(), format_size(bytes.len() as u64));
+        return Ok(());
+    }
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("无法创建目录 {}", parent.display()))?;
+    }
+    info_span!("write").in_scope(|| write_locked(path, &bytes, &opts.lock))
+}
+
+// 从 start 开始向上查找包含 .git 的目录
+fn find_repo_root(start: &Path) -> Option<PathBuf> {
+    start.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+// 写入输出文件时加上建议性的排他锁，避免多个 trxx 进程同时写同一个文件导致内容交错
+fn write_locked(path: &Path, content: &[u8], lock: &LockOptions) -> Result<()> {
+    // 先不截断地打开，拿到锁之后再清空，否则会破坏正在被其他进程写入的内容
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("无法打开输出文件 {}", path.display()))?;
+
+    let start = Instant::now();
+    while file.try_lock_exclusive().is_err() {
+        if lock.mode == LockMode::Fail {
+            anyhow::bail!("输出文件 {} 正被其他进程占用", path.display());
+        }
+        if start.elapsed() >= lock.timeout {
+            anyhow::bail!("等待输出文件 {} 的锁超时（{} 秒）", path.display(), lock.timeout.as_secs());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    file.set_len(0)?;
+    file.write_all(content)
+        .with_context(|| format!("无法写入文件 {}", path.display()))?;
+    FileExt::unlock(&file)?;
+    Ok(())
+}
+
+// 文件正文
+enum Body {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl Body {
+    fn len(&self) -> usize {
+        match self {
+            Body::Text(content) => content.len(),
+            Body::Binary(bytes) => bytes.len(),
+        }
+    }
+}
+
+fn process_file(path: &Path, rel_path: &str, extension_map: &HashMap<String, String>, is_markdown: bool, opts: &PackOptions, stats: &mut PackStats) -> Result<String> {
+    // 文件头与正文之间的注释行，还原时会被忽略
+    let mut header_comments = Vec::new();
+
+    if opts.metadata {
+        if let Ok(metadata) = path.metadata() {
+            let mut comment = format!("size={}", metadata.len());
+            if let Ok(modified) = metadata.modified() {
+                comment.push_str(&format!(" mtime={}", humantime::format_rfc3339_seconds(modified)));
+            }
+            header_comments.push(comment);
+        }
+    }
+
+    if opts.record_ctime {
+        // Linux 上能读取创建时间但无法在还原时设置，这里只作为信息记录
+        if let Ok(created) = path.metadata().and_then(|m| m.created()) {
+            header_comments.push(format!("btime={}", humantime::format_rfc3339_seconds(created)));
+        }
+    }
+
+    let is_binary = is_binary_file(path, opts);
+
+    // 不需要压缩、拆分或占位的二进制文件分块读取并编码，内存占用不随文件大小增长
+    if is_binary && !opts.compress_binary && !opts.binary_placeholder && opts.split_file_over.is_none() {
+        if opts.include_binary_metadata {
+            if let Some(meta) = image_metadata(path, rel_path) {
+                header_comments.push(meta);
+            }
+        }
+        if opts.checksum {
+            header_comments.push(format!("sha256={}", hash_file(path)?));
+        }
+        let mut result = section_header(&opts.header_prefix, rel_path, &header_comments);
+        result.push_str("```binary\n");
+        stream_base64_file(path, &mut result)
+            .with_context(|| format!("无法读取文件 {}", rel_path))?;
+        result.push_str("```\n\n");
+        return Ok(result);
+    }
+
+    let bytes = fs::read(path)?;
+    let checksum = opts.checksum.then(|| sha256_hex(&bytes));
+    let body = if is_binary {
+        if opts.include_binary_metadata {
+            if let Some(meta) = image_metadata(path, rel_path) {
+                header_comments.push(meta);
+            }
+        }
+        Body::Binary(bytes)
+    } else {
+        match String::from_utf8(bytes) {
+            Ok(content) => Body::Text(content),
+            Err(e) => match detect_encoding(e.as_bytes(), opts) {
+                Some((encoding, content)) => {
+                    debug!("文件 {} 使用 {} 编码，已转换为 UTF-8", rel_path, encoding);
+                    header_comments.push(format!("encoding={}", encoding));
+                    Body::Text(content)
+                }
+                None => match opts.on_invalid_utf8 {
+                    InvalidUtf8::Error => {
+                        return Err(e).with_context(|| format!("文件 {} 不是有效的 UTF-8 编码", rel_path));
+                    }
+                    InvalidUtf8::Skip => {
+                        warn!("跳过非 UTF-8 文件: {}", rel_path);
+                        stats.skipped.add(SkipReason::InvalidUtf8, path);
+                        return Ok(String::new());
+                    }
+                    InvalidUtf8::Binary => Body::Binary(e.into_bytes()),
+                    InvalidUtf8::Lossy => {
+                        warn!("文件 {} 不是有效的 UTF-8 编码，无效字节已被替换，还原后与原文件不一致", rel_path);
+                        header_comments.push("lossy-utf8".to_string());
+                        Body::Text(String::from_utf8_lossy(e.as_bytes()).into_owned())
+                    }
+                },
+            },
+        }
+    };
+
+    if let (Body::Binary(bytes), true) = (&body, opts.binary_placeholder) {
+        let kind = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| format!(" {}", e.to_uppercase()))
+            .unwrap_or_default();
+        header_comments.push("binary-placeholder".to_string());
+        let mut result = section_header(&opts.header_prefix, rel_path, &header_comments);
+        result.push_str(&format!("[binary file: {}{}]\n\n", format_size(bytes.len() as u64), kind));
+        return Ok(result);
+    }
+
+    if let Body::Text(content) = &body {
+        if opts.warn_mixed_eol && has_mixed_line_endings(content) {
+            warn!("文件 {} 同时包含 LF 和 CRLF 换行符", rel_path);
+        }
+    }
+
+    // 全部是 CRLF 的文件在打包文件中统一使用 LF，并记录 eol=crlf，还原时再转换回来；
+    // 混用换行符的文件，以及含有其他 \r 的文件（如 `\r\r\n`，转换后无法区分）保持原样
+    let mut eol_changed = false;
+    let body = match body {
+        Body::Text(content) => {
+            let crlf = content.matches("\r\n").count();
+            let lf = content.matches('\n').count();
+            let to_crlf = match opts.normalize_eol {
+                EolMode::Keep => crlf > 0 && crlf == lf && content.matches('\r').count() == crlf,
+                EolMode::Lf => {
+                    eol_changed = crlf > 0;
+                    false
+                }
+                EolMode::Crlf => {
+                    eol_changed = crlf < lf;
+                    lf > 0
+                }
+            };
+            if to_crlf {
+                header_comments.push("eol=crlf".to_string());
+            }
+            if to_crlf || opts.normalize_eol == EolMode::Lf {
+                Body::Text(content.replace("\r\n", "\n"))
+            } else {
+                Body::Text(content)
+            }
+        }
+        other => other,
+    };
+
+    let body = match body {
+        Body::Text(content) if opts.normalize_whitespace => {
+            let (stripped, lines) = strip_trailing_whitespace(&content);
+            if lines > 0 {
+                header_comments.push("whitespace-normalized".to_string());
+                stats.whitespace_files += 1;
+                stats.whitespace_lines += lines;
+            }
+            Body::Text(stripped)
+        }
+        other => other,
+    };
+
+    let body = match body {
+        Body::Text(content) if opts.strip_comments => {
+            let lang = file_language(path, extension_map).or_else(|| shebang_language(&content));
+            match lang.and_then(comment_syntax) {
+                Some(syntax) => {
+                    let stripped = strip_comments(&content, &syntax);
+                    if stripped != content {
+                        header_comments.push("comments-stripped".to_string());
+                    }
+                    Body::Text(stripped)
+                }
+                None => Body::Text(content),
+            }
+        }
+        other => other,
+    };
+
+    // 有损处理过的文件还原后本来就和原文件不同，不记录校验和
+    if let Some(checksum) = checksum {
+        let lossy = ["lossy-utf8", "whitespace-normalized", "comments-stripped"];
+        if !eol_changed && !header_comments.iter().any(|c| lossy.contains(&c.as_str())) {
+            header_comments.push(format!("sha256={}", checksum));
+        }
+    }
+
+    // 语言标识符，文件名和扩展名都无法识别时看脚本第一行的 shebang
+    let lang = match (file_language(path, extension_map), &body) {
+        (Some(lang), _) => lang,
+        (None, Body::Text(content)) => shebang_language(content).unwrap_or(""),
+        (None, _) => "",
+    };
+
+    // 按还原后的内容统计，不包括转义和路径注释
+    if opts.annotate {
+        if let Body::Text(content) = &body {
+            header_comments.push(format!("{} lines, {} chars", content.lines().count(), content.chars().count()));
+        }
+    }
+
+    // 行号在路径注释之前加上，与原文件的行对应
+    let body = match body {
+        Body::Text(content) if opts.line_numbers => {
+            header_comments.push("line-numbers".to_string());
+            Body::Text(number_lines(&content))
+        }
+        other => other,
+    };
+
+    let body = match body {
+        Body::Text(content) if opts.path_comments => match path_comment(lang, rel_path) {
+            Some(comment) => {
+                header_comments.push("path-comment".to_string());
+                Body::Text(format!("{}\n{}", comment, content))
+            }
+            None => Body::Text(content),
+        },
+        other => other,
+    };
+
+    // 超大文件拆分成多段，每段都有自己的文件头，还原时按序号拼接
+    let parts = match opts.split_file_over {
+        Some(limit) if body.len() > limit => split_body(body, limit),
+        _ => vec![body],
+    };
+    let total = parts.len();
+
+    let mut result = String::new();
+    for (index, part) in parts.into_iter().enumerate() {
+        let mut comments = header_comments.clone();
+        if total > 1 {
+            comments.push(format!("part={}/{}", index + 1, total));
+        }
+        result.push_str(&render_section(rel_path, comments, part, lang, is_markdown, opts)?);
+    }
+    
+    Ok(result)
+}
+
+// 一种语言的注释语法
+struct CommentSyntax {
+    line: Option<&'static str>,
+    block: Option<(&'static str, &'static str)>,
+    // 字符串的引号，引号中的内容不会被当成注释
+    quotes: &'static [char],
+    // Rust 的 '"' 这类字符字面量，单引号也可能是生命周期，不能直接当成引号
+    char_literals: bool,
+    // 块注释可以嵌套，例如 Rust 的 /* a /* b */ c */
+    nested_blocks: bool,
+    // JavaScript 的正则表达式字面量，其中的 // 和引号不是注释或字符串
+    regex_literals: bool,
+}
+
+// 只处理注释语法明确的语言；Dockerfile 开头的 # syntax= 这类注释有实际作用，不在其中
+fn comment_syntax(lang: &str) -> Option<CommentSyntax> {
+    let (line, block, quotes): (_, _, &[char]) = match lang {
+        "rust" => (Some("//"), Some(("/*", "*/")), &['"']),
+        "c" | "cpp" | "c/c++ header" | "java" | "php" | "groovy" | "csharp" | "kotlin" | "swift" => {
+            (Some("//"), Some(("/*", "*/")), &['"', '\''])
+        }
+        "javascript" | "typescript" | "jsx" | "tsx" | "go" => (Some("//"), Some(("/*", "*/")), &['"', '\'', '`']),
+        // url(//cdn...) 中的 // 不是注释，CSS 只删除块注释
+        "css" => (None, Some(("/*", "*/")), &['"', '\'']),
+        "jsonc" => (Some("//"), Some(("/*", "*/")), &['"']),
+        "json5" => (Some("//"), Some(("/*", "*/")), &['"', '\'']),
+        "python" | "ruby" | "perl" | "r" | "bash" | "zsh" | "fish" | "yaml" | "toml" | "makefile" | "cmake" => {
+            (Some("#"), None, &['"', '\''])
+        }
+        "powershell" => (Some("#"), Some(("<#", "#>")), &['"', '\'']),
+        "sql" => (Some("--"), Some(("/*", "*/")), &['\'', '"']),
+        // 文本中的单引号多是撇号，HTML 和 XML 不识别字符串
+        "html" | "xml" => (None, Some(("<!--", "-->")), &[]),
+        _ => return None,
+    };
+    Some(CommentSyntax {
+        line,
+        block,
+        quotes,
+        char_literals: lang == "rust",
+        nested_blocks: matches!(lang, "rust" | "swift" | "kotlin"),
+        regex_literals: matches!(lang, "javascript" | "typescript" | "jsx" | "tsx"),
+    })
+}
+
+// 删除注释，字符串中的内容保持不变；删除注释后只剩空白的行整行删除。
+// 只是简单的词法扫描：认识 Rust、Swift、Kotlin 的嵌套块注释和 JavaScript 的正则表达式字面量，
+// 不认识原始字符串、heredoc、模板字符串中 ${} 里的代码、Perl 和 Ruby 的正则表达式等写法，遇到不配对的引号时宁可少删
+fn strip_comments(content: &str, syntax: &CommentSyntax) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    // 脚本第一行的 shebang 不是注释
+    if syntax.line == Some("#") && rest.starts_with("#!") {
+        let end = rest.find('\n').unwrap_or(rest.len());
+        out.push_str(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    // 当前行在 out 中的开头，以及这一行是否删除过注释
+    let mut line_start = 0;
+    let mut removed = false;
+    let mut quote = None;
+    while let Some(c) = rest.chars().next() {
+        let len = if let Some(q) = quote {
+            // 跳过转义字符，避免 "\"" 提前结束字符串
+            if c == q {
+                quote = None;
+            }
+            if c == '\\' {
+                1 + rest[1..].chars().next().map_or(0, char::len_utf8)
+            } else {
+                c.len_utf8()
+            }
+        } else if c == '\n' {
+            if !finish_stripped_line(&mut out, line_start, removed) {
+                rest = &rest[1..];
+                removed = false;
+                continue;
+            }
+            1
+        } else if let Some(end) = syntax.block.and_then(|(open, close)| block_comment_len(rest, open, close, syntax.nested_blocks)) {
+            rest = &rest[end..];
+            removed = true;
+            continue;
+        } else if syntax.line.is_some_and(|marker| {
+            // # 只有在行首或空白之后才是注释，避免误删 $#、${#var} 这类写法
+            rest.starts_with(marker) && (marker != "#" || out[line_start..].chars().last().is_none_or(char::is_whitespace))
+        }) {
+            // 保留行尾的 \r，CRLF 文件删除注释后换行符不变
+            let end = rest.find('\n').unwrap_or(rest.len());
+            let end = if rest[..end].ends_with('\r') { end - 1 } else { end };
+            rest = &rest[end..];
+            removed = true;
+            continue;
+        } else if let Some(len) = (c == '/' && syntax.regex_literals && regex_allowed(&out))
+            .then(|| regex_literal_len(rest))
+            .flatten()
+        {
+            len
+        } else if c == '\'' && syntax.char_literals {
+            char_literal_len(rest)
+        } else {
+            if syntax.quotes.contains(&c) {
+                quote = Some(c);
+            }
+            c.len_utf8()
+        };
+        let (text, remaining) = rest.split_at(len);
+        out.push_str(text);
+        if text.contains('\n') {
+            line_start = out.len();
+            removed = false;
+        }
+        rest = remaining;
+    }
+    finish_stripped_line(&mut out, line_start, removed);
+    out
+}
+
+// 删除过注释的行去掉行尾空白（保留 \r），只剩空白时整行删除；返回这一行是否保留
+fn finish_stripped_line(out: &mut String, line_start: usize, removed: bool) -> bool {
+    if !removed {
+        return true;
+    }
+    let crlf = out.ends_with('\r');
+    let kept = out[line_start..].trim_end().len();
+    out.truncate(line_start + kept);
+    if kept == 0 {
+        return false;
+    }
+    if crlf {
+        out.push('\r');
+    }
+    true
+}
+
+// 以 open 开头的块注释的长度，没有结束时一直到内容末尾
+fn block_comment_len(rest: &str, open: &str, close: &str, nested: bool) -> Option<usize> {
+    let mut i = open.len();
+    let mut depth = 1;
+    rest.strip_prefix(open)?;
+    while i < rest.len() {
+        if nested && rest[i..].starts_with(open) {
+            depth += 1;
+            i += open.len();
+        } else if rest[i..].starts_with(close) {
+            depth -= 1;
+            i += close.len();
+            if depth == 0 {
+                return Some(i);
+            }
+        } else {
+            i += rest[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    Some(rest.len())
+}
+
+// 根据前一个记号判断 / 是正则表达式的开头还是除号：行首、运算符和括号之后，
+// 以及 return、typeof 等关键字之后是正则表达式
+fn regex_allowed(before: &str) -> bool {
+    let before = before.trim_end();
+    match before.chars().last() {
+        None => true,
+        Some(c) if "(,=:[!&|?{};+-*%<>~^".contains(c) => true,
+        Some(c) if c.is_alphanumeric() || c == '_' || c == '$' => {
+            let word_start = before.rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$')).map_or(0, |i| i + 1);
+            matches!(&before[word_start..], "return" | "typeof" | "case" | "in" | "of" | "delete" | "void"
+                | "throw" | "new" | "yield" | "await" | "instanceof" | "else" | "do")
+        }
+        _ => false,
+    }
+}
+
+// 以 / 开头的正则表达式字面量的长度（不含标志），字符类 [...] 中的 / 不结束正则表达式；
+// 同一行中没有结束的 / 时不是正则表达式
+fn regex_literal_len(rest: &str) -> Option<usize> {
+    if rest[1..].starts_with(['/', '*']) {
+        return None;
+    }
+    let mut in_class = false;
+    let mut chars = rest.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' | '\r' => return None,
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '/' if !in_class => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+// Rust 中以单引号开头的字符字面量（如 '"'、'\''、'\u{1F600}'）的长度，生命周期只算单引号本身
+fn char_literal_len(rest: &str) -> usize {
+    let mut chars = rest.char_indices().skip(1);
+    match chars.next() {
+        // 先跳过反斜杠后面被转义的字符，'\'' 中间的单引号不是结尾
+        Some((_, '\\')) if rest[2..].starts_with("u{") => rest[4..]
+            .find('}')
+            .filter(|&i| rest[4 + i + 1..].starts_with('\''))
+            .map_or(1, |i| i + 6),
+        Some((_, '\\')) => rest.get(3..).and_then(|tail| tail.find('\'')).map_or(1, |i| i + 4),
+        Some((_, c)) => match chars.next() {
+            Some((i, '\'')) if c != '\'' => i + 1,
+            _ => 1,
+        },
+        None => 1,
+    }
+}
+
+// 检测非 UTF-8 文本的编码并转换为 UTF-8；只有能按原编码逐字节还原时才返回结果
+fn detect_encoding(bytes: &[u8], opts: &PackOptions) -> Option<(&'static str, String)> {
+    if !opts.encoding_detect {
+        return None;
+    }
+    // 带 BOM 的 UTF-16 文件（Windows 生成的配置文件和 PowerShell 脚本常见），去掉 BOM 后转换，还原时写回
+    if let Some(encoding) = utf16_bom(bytes) {
+        let (content, had_errors) = encoding.decode_with_bom_removal(bytes);
+        return (!had_errors).then(|| (encoding.name(), content.into_owned()));
+    }
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, false);
+    let (content, had_errors) = encoding.decode_without_bom_handling(bytes);
+    if had_errors {
+        return None;
+    }
+    let (encoded, _, unmappable) = encoding.encode(&content);
+    if unmappable || encoded != bytes {
+        return None;
+    }
+    Some((encoding.name(), content.into_owned()))
+}
+
+// 根据开头的 BOM 识别 UTF-16LE 或 UTF-16BE
+fn utf16_bom(bytes: &[u8]) -> Option<&'static encoding_rs::Encoding> {
+    encoding_rs::Encoding::for_bom(bytes)
+        .map(|(encoding, _)| encoding)
+        .filter(|encoding| is_utf16(encoding))
+}
+
+fn is_utf16(encoding: &encoding_rs::Encoding) -> bool {
+    encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE
+}
+
+// encoding_rs 不能编码成 UTF-16，这里手动转换，并在开头写回 BOM
+fn encode_utf16_with_bom(text: &str, encoding: &encoding_rs::Encoding) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity((text.len() + 1) * 2);
+    for unit in std::iter::once(0xfeff).chain(text.encode_utf16()) {
+        if encoding == encoding_rs::UTF_16LE {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        } else {
+            bytes.extend_from_slice(&unit.to_be_bytes());
+        }
+    }
+    bytes
+}
+
+// 文件头和注释行，以空行结束
+fn section_header(prefix: &str, rel_path: &str, comments: &[String]) -> String {
+    let mut header = format!("{}{}\n", prefix, rel_path);
+    for comment in comments {
+        header.push_str(&format!("<!-- {} -->\n", comment));
+    }
+    header.push('\n');
+    header
+}
+
+// base64 每行 76 个字符，对应 57 个字节
+const BASE64_LINE_BYTES: usize = 57;
+
+// 按行写入 base64，每行以换行结束；空内容写入一个空行，保证还原时仍能得到空文件
+fn push_base64_lines(bytes: &[u8], out: &mut String) {
+    if bytes.is_empty() {
+        out.push('\n');
+    }
+    for chunk in bytes.chunks(BASE64_LINE_BYTES) {
+        base64::encode_config_buf(chunk, base64::STANDARD, out);
+        out.push('\n');
+    }
+}
+
+// 分块读取文件并按行写入 base64
+fn stream_base64_file(path: &Path, out: &mut String) -> Result<()> {
+    let mut file = File::open(path)?;
+    // 预先分配编码后的长度，避免输出缓冲区反复扩容
+    let len = file.metadata()?.len() as usize;
+    out.reserve(len.div_ceil(BASE64_LINE_BYTES) * 77 + 1);
+    let mut buf = vec![0u8; BASE64_LINE_BYTES * 1024];
+    let mut total = 0;
+    loop {
+        let n = read_full(&mut file, &mut buf)?;
+        if n > 0 || total == 0 {
+            push_base64_lines(&buf[..n], out);
+        }
+        total += n;
+        if n < buf.len() {
+            return Ok(());
+        }
+    }
+}
+
+// 尽量读满 buf，只有到达文件末尾时才会少于 buf 的长度
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+// 分块计算文件的 SHA-256
+fn hash_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("无法读取文件 {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).with_context(|| format!("无法读取文件 {}", path.display()))?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// 按语言生成包含文件路径的单行注释，没有注释语法的语言（如 JSON、纯文本）返回 None
+fn path_comment(lang: &str, rel_path: &str) -> Option<String> {
+    let (start, end) = match lang {
+        "rust" | "javascript" | "typescript" | "java" | "cpp" | "c" | "go" | "php"
+        | "jsx" | "tsx" | "c/c++ header" | "jsonc" | "json5" => ("//", ""),
+        "python" | "ruby" | "yaml" | "toml" | "bash" | "powershell" | "env"
+        | "gitignore" | "conf" | "ini" => ("#", ""),
+        "sql" | "lua" => ("--", ""),
+        "css" => ("/*", " */"),
+        "html" | "xml" | "vue" | "markdown" => ("<!--", " -->"),
+        "batch file" => ("REM", ""),
+        _ => return None,
+    };
+    Some(format!("{} {}{}", start, rel_path, end))
+}
+
+// 每行前加上 `行号 | `，行号按总行数右对齐
+fn number_lines(content: &str) -> String {
+    let width = content.split_inclusive('\n').count().to_string().len();
+    content.split_inclusive('\n')
+        .enumerate()
+        .map(|(i, line)| format!("{:>width$} | {}", i + 1, line))
+        .collect()
+}
+
+// 去掉 number_lines 加的行号前缀，返回结果和没有行号前缀（原样保留）的行数
+fn strip_line_numbers(bytes: &[u8]) -> (Vec<u8>, usize) {
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut unnumbered = 0;
+    for line in bytes.split_inclusive(|&b| b == b'\n') {
+        let digits = line.iter().position(|&b| b != b' ').unwrap_or(line.len());
+        let end = digits + line[digits..].iter().take_while(|b| b.is_ascii_digit()).count();
+        match line[end..].strip_prefix(b" | ") {
+            Some(rest) if end > digits => result.extend_from_slice(rest),
+            _ => {
+                unnumbered += 1;
+                result.extend_from_slice(line);
+            }
+        }
+    }
+    (result, unnumbered)
+}
+
+fn render_section(rel_path: &str, mut comments: Vec<String>, body: Body, lang: &str, is_markdown: bool, opts: &PackOptions) -> Result<String> {
+    let mut result = String::new();
+
+    // 只有压缩后确实更小时才使用 gzip
+    let body = match body {
+        Body::Binary(bytes) if opts.compress_binary => match gzip_if_smaller(&bytes)? {
+            Some(compressed) => {
+                comments.push("encoding=base64+gzip".to_string());
+                Body::Binary(compressed)
+            }
+            None => Body::Binary(bytes),
+        },
+        other => other,
+    };
+
+    // 只有确实转义了某些行时才需要标记，还原时根据标记反转义
+    let escape = opts.escape_markdown && is_markdown
+        && matches!(&body, Body::Text(content) if content.split_terminator('\n').any(needs_markdown_escape));
+    if escape {
+        comments.push("markdown-escaped".to_string());
+    }
+
+    // 添加文件头
+    result.push_str(&section_header(&opts.header_prefix, rel_path, &comments));
+    
+    match body {
+        Body::Binary(bytes) => {
+            // 处理二进制文件（图片）
+            result.push_str("```binary\n");
+            push_base64_lines(&bytes, &mut result);
+            result.push_str("```\n\n");
+        }
+        Body::Text(content) => {
+            // 代码块的反引号比内容中最长的一串反引号还多，内容中的 ``` 不会被当成代码块结束
+            let fence = "`".repeat(longest_backtick_run(&content).max(2) + 1);
+
+            // 添加语言标识符
+            result.push_str(&format!("{}{}", fence, lang));
+            result.push_str("\n\n");
+            
+            // 内容原样写入，末尾的换行也保留，还原时只去掉代码块首尾各一个空行
+            result.push_str(&escape_markdown_content(&content, escape));
+            result.push_str("\n\n");
+            result.push_str(&fence);
+            result.push_str("\n\n");
+        }
+    }
+    
+    Ok(result)
+}
+
+fn longest_backtick_run(content: &str) -> usize {
+    content.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+// 行首连续反引号的数量，至少 3 个才是代码块的边界
+fn fence_len(line: &str) -> Option<usize> {
+    let len = line.len() - line.trim_start_matches('`').len();
+    (len >= 3).then_some(len)
+}
+
+// 文本尽量在换行处拆分，单行超过上限时按字符边界拆分；二进制按字节拆分
+fn split_body(body: Body, limit: usize) -> Vec<Body> {
+    let limit = limit.max(1);
+    match body {
+        Body::Binary(bytes) => bytes.chunks(limit).map(|c| Body::Binary(c.to_vec())).collect(),
+        Body::Text(content) => {
+            let mut parts = Vec::new();
+            let mut rest = content.as_str();
+            while rest.len() > limit {
+                let mut end = limit;
+                while !rest.is_char_boundary(end) {
+                    end -= 1;
+                }
+                if end == 0 {
+                    end = rest.chars().next().map(char::len_utf8).unwrap_or(rest.len());
+                }
+                let cut = rest[..end].rfind('\n').map(|i| i + 1).unwrap_or(end);
+                parts.push(Body::Text(rest[..cut].to_string()));
+                rest = &rest[cut..];
+            }
+            parts.push(Body::Text(rest.to_string()));
+            parts
+        }
+    }
+}
+
+fn gzip(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?)
+}
+
+fn gzip_if_smaller(bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+    let compressed = gzip(bytes)?;
+    Ok((compressed.len() < bytes.len()).then_some(compressed))
+}
+
+// 只解析图片头部，获取格式和尺寸，例如 `image=png 1920x1080`
+fn image_metadata(path: &Path, rel_path: &str) -> Option<String> {
+    let reader = match image::ImageReader::open(path).and_then(|r| r.with_guessed_format()) {
+        Ok(reader) => reader,
+        Err(e) => {
+            warn!("无法读取图片 {} 的元信息: {}", rel_path, e);
+            return None;
+        }
+    };
+    let format = reader.format()?.extensions_str().first()?.to_string();
+    match reader.into_dimensions() {
+        Ok((width, height)) => Some(format!("image={} {}x{}", format, width, height)),
+        Err(e) => {
+            warn!("无法读取图片 {} 的元信息: {}", rel_path, e);
+            None
+        }
+    }
+}
+
+// 删除每行末尾的空白字符，保留原有的换行符；返回处理后的内容和被修改的行数
+fn strip_trailing_whitespace(content: &str) -> (String, usize) {
+    let mut result = String::with_capacity(content.len());
+    let mut changed = 0;
+    for line in content.split_inclusive('\n') {
+        let ending_len = if line.ends_with("\r\n") {
+            2
+        } else if line.ends_with('\n') {
+            1
+        } else {
+            0
+        };
+        let (text, ending) = line.split_at(line.len() - ending_len);
+        let trimmed = text.trim_end();
+        if trimmed.len() != text.len() {
+            changed += 1;
+        }
+        result.push_str(trimmed);
+        result.push_str(ending);
+    }
+    (result, changed)
+}
+
+fn has_mixed_line_endings(content: &str) -> bool {
+    let crlf = content.matches("\r\n").count();
+    let lf = content.matches('\n').count();
+    crlf > 0 && crlf < lf
+}
+
+// escape_markdown_content 的逆操作：只去掉转义时加上的那一个反斜杠
+fn unescape_markdown_content(content: &str) -> String {
+    content.split_inclusive('\n')
+        .map(|line| match line.strip_prefix('\\') {
+            Some(rest) if needs_markdown_escape(rest) => rest,
+            _ => line,
+        })
+        .collect()
+}
+
+// 展开输入：目录按自然顺序取其中的所有文件，不存在的路径按 glob 模式展开
+fn expand_inputs(inputs: &[String]) -> Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        let mut paths = if input == "-" || path.is_file() {
+            expanded.push(input.clone());
+            continue;
+        } else if path.is_dir() {
+            fs::read_dir(path)
+                .with_context(|| format!("无法读取目录 {}", input))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && !p.file_name().is_some_and(|n| n.to_string_lossy().starts_with('.')))
+                .map(|p| p.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        } else if input.contains(['*', '?', '[']) {
+            glob(input)
+                .with_context(|| format!("无效的 glob 模式 {}", input))?
+                .filter_map(|entry| entry.ok())
+                .filter(|p| p.is_file())
+                .map(|p| p.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        } else {
+            // 交给后面读取文件时报告错误
+            expanded.push(input.clone());
+            continue;
+        };
+        if paths.is_empty() {
+            anyhow::bail!("{} 中没有找到打包文件", input);
+        }
+        paths.sort_by(|a, b| natord::compare(a, b));
+        expanded.extend(paths);
+    }
+    Ok(expanded)
+}
+
+// 按顺序读取多个打包文件并拼接，拆分到不同文件中的分段也能正确拼接
+fn read_archives(inputs: &[String]) -> Result<String> {
+    let mut content = String::new();
+    for input_path in &expand_inputs(inputs)? {
+        // `-` 表示从标准输入读取
+        let mut bytes = if input_path == "-" {
+            let mut bytes = Vec::new();
+            std::io::stdin().read_to_end(&mut bytes).context("无法读取标准输入")?;
+            bytes
+        } else {
+            fs::read(input_path)
+                .with_context(|| format!("无法读取文件 {}", input_path))?
+        };
+        // 根据 gzip 文件头识别压缩过的打包文件，不依赖 .gz 扩展名
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            let mut decoded = Vec::new();
+            GzDecoder::new(bytes.as_slice())
+                .read_to_end(&mut decoded)
+                .with_context(|| format!("无法解压文件 {}", input_path))?;
+            bytes = decoded;
+        }
+        if is_tar(&bytes) || is_zip(&bytes) {
+            let part = if is_zip(&bytes) {
+                zip_to_json(&bytes).with_context(|| format!("无法读取 zip 压缩包 {}", input_path))?
+            } else {
+                tar_to_json(&bytes).with_context(|| format!("无法读取 tar 归档 {}", input_path))?
+            };
+            content.push_str(&part);
+            content.push('\n');
+            continue;
+        }
+        let part = String::from_utf8(bytes)
+            .with_context(|| format!("文件 {} 不是有效的 UTF-8 文本", input_path))?;
+        content.push_str(&part);
+        // 只在缺少换行时补上，被截断的打包文件末尾不会多出一个空行
+        if !part.ends_with('\n') {
+            content.push('\n');
+        }
+    }
+    Ok(content)
+}
+
+pub fn revert_files(inputs: &[String], opts: &RevertOptions) -> Result<()> {
+    let content = read_archives(inputs)?;
+    revert_archive(&content, opts)?;
+    info!("文件已还原完成");
+    Ok(())
+}
+
+// 将打包内容中的文件还原到输出目录下
+fn revert_archive(content: &str, opts: &RevertOptions) -> Result<()> {
+    if let Some(dir) = &opts.output_dir {
+        if !opts.no_create_dirs {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("无法创建目录 {}", dir.display()))?;
+        }
+    }
+
+    // 创建一个 Set 来记录已创建的目录
+    let mut created_dirs = std::collections::HashSet::new();
+    // 记录已写入的文件，同一路径出现多次时后面的内容覆盖前面的
+    let mut written = std::collections::HashSet::new();
+
+    let preserve_ctime = opts.preserve_ctime && CAN_SET_BTIME;
+    if opts.preserve_ctime && !CAN_SET_BTIME {
+        warn!("当前平台不支持设置文件创建时间，--preserve-ctime 将被忽略");
+    }
+    let only = glob_patterns(&opts.only)?;
+    let mut selected = 0;
+
+    let dirs = for_each_file(content, opts.header_prefix(), |file| {
+        if !opts.selects(&file.path, &only) {
+            return Ok(());
+        }
+        selected += 1;
+        if file.is_placeholder() {
+            if opts.strict {
+                anyhow::bail!("文件 {} 打包时只保留了占位说明，无法还原", file.path);
+            }
+            warn!("文件 {} 打包时只保留了占位说明，跳过", file.path);
+            return Ok(());
+        }
+        if !written.insert(file.path.clone()) {
+            warn!("文件 {} 出现了多次，使用后出现的内容", file.path);
+        }
+        // 被截断的文件本来就不完整，不检查校验和
+        if let Some(expected) = file.comment_value("sha256").filter(|_| !file.comments.iter().any(|c| c == "truncated")) {
+            if sha256_hex(&file.bytes) != expected {
+                anyhow::bail!("文件 {} 的校验和不匹配，打包文件可能已损坏", file.path);
+            }
+        }
+        let target = target_path(&file.path, opts.output_dir.as_deref(), opts.allow_absolute)?;
+        let target = target.to_string_lossy();
+        save_content(&target, &file.bytes, &mut created_dirs, opts)?;
+        if let Some(mode) = file.comment_value("mode") {
+            restore_mode(&target, mode)?;
+        }
+        if preserve_ctime {
+            if let Some(btime) = file.comment_value("btime") {
+                restore_btime(&target, btime)?;
+            }
+        }
+        if opts.preserve_mtime {
+            if let Some(mtime) = file.comment_value("mtime") {
+                restore_mtime(&target, mtime)?;
+            }
+        }
+        Ok(())
+    })?;
+
+    for dir in dirs.iter().filter(|dir| opts.selects(dir, &only)) {
+        selected += 1;
+        let target = target_path(dir, opts.output_dir.as_deref(), opts.allow_absolute)?;
+        let created = if opts.no_create_dirs {
+            fs::create_dir(&target).or_else(|e| if target.is_dir() { Ok(()) } else { Err(e) })
+        } else {
+            fs::create_dir_all(&target)
+        };
+        created.with_context(|| format!("无法创建目录 {}", target.display()))?;
+    }
+
+    if selected == 0 && !opts.only.is_empty() {
+        warn!("打包文件中没有与 --only 匹配的文件");
+    }
+    Ok(())
+}
+
+// 还原后的文件路径：去掉 `.`，拒绝包含 `..` 的路径；绝对路径只有 allow_absolute 时才会写回原来的位置，
+// 避免不可信的打包文件写到目标目录之外
+fn target_path(path: &str, output_dir: Option<&Path>, allow_absolute: bool) -> Result<PathBuf> {
+    use std::path::Component;
+
+    // 兼容旧版本在 Windows 上生成的以 \ 分隔的路径；/ 在各平台上都会转换成本地的分隔符
+    let separated = path.replace('\\', "/");
+    let original = Path::new(&separated);
+    if original.components().any(|c| c == Component::ParentDir) {
+        anyhow::bail!("文件路径 {} 包含 ..，可能写到目标目录之外，已拒绝还原", path);
+    }
+    let normalized: PathBuf = original.components().filter(|c| *c != Component::CurDir).collect();
+    if normalized.as_os_str().is_empty() {
+        anyhow::bail!("文件路径 {} 无效", path);
+    }
+    if normalized.has_root() || normalized.is_absolute() {
+        if !allow_absolute {
+            anyhow::bail!("文件路径 {} 是绝对路径，已拒绝还原；确认打包文件可信时可以使用 --allow-absolute", path);
+        }
+        return Ok(normalized);
+    }
+    Ok(match output_dir {
+        Some(dir) => dir.join(normalized),
+        None => normalized,
+    })
+}
+
+// 只比较打包文件与目标目录下的文件，不写入任何内容；返回是否有文件会被覆盖
+pub fn preview_revert(inputs: &[String], opts: &RevertOptions) -> Result<bool> {
+    let content = read_archives(inputs)?;
+
+    let only = glob_patterns(&opts.only)?;
+    let (mut create, mut overwrite, mut identical) = (0, 0, 0);
+    for_each_file(&content, opts.header_prefix(), |entry| {
+        if !opts.selects(&entry.path, &only) {
+            return Ok(());
+        }
+        if entry.is_placeholder() {
+            println!("跳过  {}（二进制占位，无法还原）", entry.path);
+            return Ok(());
+        }
+        let target = target_path(&entry.path, opts.output_dir.as_deref(), opts.allow_absolute)?;
+        let (file, bytes) = (target.to_string_lossy(), entry.bytes);
+        match fs::read(target.as_path()) {
+            Ok(existing) if existing == bytes => {
+                println!("相同  {}", file);
+                identical += 1;
+            }
+            Ok(_) => {
+                println!("覆盖  {}", file);
+                overwrite += 1;
+            }
+            Err(_) => {
+                println!("新建  {}", file);
+                create += 1;
+            }
+        }
+        Ok(())
+    })?;
+
+    println!("共 {} 个文件：新建 {} 个，覆盖 {} 个，相同 {} 个", create + overwrite + identical, create, overwrite, identical);
+    Ok(overwrite > 0)
+}
+
+// 在内存中还原打包文件，与 dir 下的文件逐字节比较；返回是否完全一致。
+// dir 中按默认规则会被打包、但打包文件中没有的文件记为多余
+pub fn verify_archive(inputs: &[String], dir: &Path, opts: &PackOptions) -> Result<bool> {
+    let content = read_archives(inputs)?;
+    let root = fs::canonicalize(dir)
+        .with_context(|| format!("无法访问目录 {}", dir.display()))?;
+
+    let mut archived = std::collections::HashSet::new();
+    let (mut identical, mut differing, mut missing) = (0, 0, 0);
+    for_each_file(&content, &opts.header_prefix, |entry| {
+        // 打包时使用了 --root-prefix 时去掉前缀再与目录比较，多个仓库拼接的打包文件中其他前缀下的文件不参与比较
+        let path = match &opts.root_prefix {
+            Some(prefix) => match entry.path.strip_prefix(prefix.as_str()).and_then(|p| p.strip_prefix('/')) {
+                Some(path) => path,
+                None => return Ok(()),
+            },
+            None => entry.path.as_str(),
+        };
+        if entry.is_placeholder() {
+            println!("跳过  {}（二进制占位，无法比较）", path);
+            return Ok(());
+        }
+        let rel = target_path(path, None, false)?;
+        match fs::read(root.join(&rel)) {
+            Ok(existing) if existing == entry.bytes => identical += 1,
+            Ok(_) => {
+                println!("不同  {}", rel.display());
+                differing += 1;
+            }
+            Err(_) => {
+                println!("缺失  {}", rel.display());
+                missing += 1;
+            }
+        }
+        archived.insert(rel);
+        Ok(())
+    })?;
+
+    let mut stats = PackStats::default();
+    let mut extra = 0;
+    for path in collect_files(&root, opts, &mut stats.skipped)? {
+        let rel = path.strip_prefix(&root).unwrap_or(&path);
+        if !archived.contains(rel) {
+            println!("多余  {}", rel.display());
+            extra += 1;
+        }
+    }
+
+    println!("相同 {} 个，不同 {} 个，缺失 {} 个，多余 {} 个", identical, differing, missing, extra);
+    Ok(differing + missing + extra == 0)
+}
+
+// 打包文件中的一个文件段落
+struct Section {
+    path: String,
+    body: String,
+    is_binary: bool,
+    // 是否出现过代码块；空文件的正文为空，不能用正文是否为空来判断
+    has_body: bool,
+    // 文件头与正文之间的注释，例如 `encoding=base64+gzip`
+    comments: Vec<String>,
+}
+
+impl Section {
+    // 有代码块、二进制占位或者引用了其他文件的段落才对应一个要还原的文件
+    fn is_complete(&self) -> bool {
+        self.has_body || self.has_comment("binary-placeholder") || self.comments.iter().any(|c| c.starts_with("same-as="))
+    }
+
+    fn has_comment(&self, comment: &str) -> bool {
+        self.comments.iter().any(|c| c == comment)
+    }
+
+    // 拆分文件的分段信息 `part=序号/总数`
+    fn part(&self) -> Result<Option<(usize, usize)>> {
+        let Some(value) = self.comments.iter().find_map(|c| c.strip_prefix("part=")) else {
+            return Ok(None);
+        };
+        let parsed = value
+            .split_once('/')
+            .and_then(|(index, total)| Some((index.parse().ok()?, total.parse().ok()?)))
+            .filter(|&(index, total)| index >= 1 && index <= total);
+        match parsed {
+            Some(part) => Ok(Some(part)),
+            None => anyhow::bail!("文件 {} 的分段标记 part={} 无效", self.path, value),
+        }
+    }
+}
+
+// 还原出来的一个完整文件
+struct ArchiveFile {
+    path: String,
+    bytes: Vec<u8>,
+    // 文件头中的注释，拆分过的文件取第一段的注释
+    comments: Vec<String>,
+}
+
+impl ArchiveFile {
+    // 打包时使用了 --binary-placeholder，没有保存内容
+    fn is_placeholder(&self) -> bool {
+        self.comments.iter().any(|c| c == "binary-placeholder")
+    }
+
+    // --dedup 打包时与前面某个文件内容相同，只记录了原文件路径；路径中可能有空格，不能用 comment_value
+    fn same_as(&self) -> Option<&str> {
+        self.comments.iter().find_map(|c| c.strip_prefix("same-as="))
+    }
+
+    fn new(path: String, mut bytes: Vec<u8>, comments: Vec<String>) -> Result<Self> {
+        // 去掉打包时插入的路径注释行
+        if comments.iter().any(|c| c == "path-comment") {
+            let first_line = bytes.iter().position(|&b| b == b'\n').map(|i| i + 1).unwrap_or(bytes.len());
+            bytes.drain(..first_line);
+        }
+        if comments.iter().any(|c| c == "line-numbers") {
+            let (stripped, unnumbered) = strip_line_numbers(&bytes);
+            if unnumbered > 0 {
+                warn!("文件 {} 有 {} 行没有行号前缀，已原样还原", path, unnumbered);
+            }
+            bytes = stripped;
+        }
+        if comments.iter().any(|c| c == "eol=crlf") {
+            bytes = restore_crlf(&bytes);
+        }
+        let mut file = ArchiveFile { path, bytes, comments };
+        file.restore_encoding()?;
+        Ok(file)
+    }
+
+    // 打包时从其他编码转换成了 UTF-8 的文本，还原时转换回原来的编码
+    fn restore_encoding(&mut self) -> Result<()> {
+        let Some(label) = self.comment_value("encoding").filter(|&e| e != "base64+gzip") else {
+            return Ok(());
+        };
+        let encoding = encoding_rs::Encoding::for_label(label.as_bytes())
+            .with_context(|| format!("文件 {} 的编码 {} 无法识别", self.path, label))?;
+        let text = String::from_utf8_lossy(&self.bytes);
+        if is_utf16(encoding) {
+            self.bytes = encode_utf16_with_bom(&text, encoding);
+            return Ok(());
+        }
+        let (encoded, _, unmappable) = encoding.encode(&text);
+        if unmappable {
+            anyhow::bail!("文件 {} 包含无法用 {} 编码表示的字符", self.path, label);
+        }
+        self.bytes = encoded.into_owned();
+        Ok(())
+    }
+
+    // 读取 `key=value` 形式的注释
+    // 一行注释中可以有多个以空格分隔的 key=value，例如 `size=1234 mtime=...`
+    fn comment_value(&self, key: &str) -> Option<&str> {
+        self.comments.iter()
+            .flat_map(|c| c.split_whitespace())
+            .find_map(|c| c.strip_prefix(key)?.strip_prefix('='))
+    }
+}
+
+// 解析打包文件并还原出每个文件的完整内容，拆分过的文件会在所有分段到齐后拼接；返回记录的空目录
+fn for_each_file(content: &str, prefix: &str, mut on_file: impl FnMut(ArchiveFile) -> Result<()>) -> Result<Vec<String>> {
+    // 路径 -> (第一段的注释, 各分段内容)
+    type Pending = (Vec<String>, Vec<Option<Vec<u8>>>);
+    let mut pending: HashMap<String, Pending> = HashMap::new();
+
+    // 只保留被其他文件引用的原文件内容，引用它的文件直接复制一份
+    let referenced: std::collections::HashSet<&str> = content.lines()
+        .filter_map(|line| line.trim_end().strip_prefix("<!-- same-as=")?.strip_suffix(" -->"))
+        .collect();
+    // 原文件只有占位说明时记为 None，引用它的文件同样按占位处理
+    let mut originals: HashMap<String, Option<Vec<u8>>> = HashMap::new();
+    let mut on_file = |mut file: ArchiveFile| {
+        if let Some(original) = file.same_as().map(str::to_string) {
+            match originals.get(&original)
+                .with_context(|| format!("文件 {} 引用的 {} 没有出现在它之前", file.path, original))?
+            {
+                Some(bytes) => file.bytes = bytes.clone(),
+                None => file.comments.push("binary-placeholder".to_string()),
+            }
+        }
+        if referenced.contains(file.path.as_str()) {
+            originals.insert(file.path.clone(), (!file.is_placeholder()).then(|| file.bytes.clone()));
+        }
+        on_file(file)
+    };
+
+    if is_json_archive(content) {
+        // 同时还原多个 JSON 打包文件时，它们之间只以换行分隔
+        for archive in serde_json::Deserializer::from_str(content).into_iter::<JsonArchive>() {
+            for file in archive.context("无法解析 JSON 格式的打包文件")?.into_files() {
+                let bytes = if file.binary {
+                    decode_base64_lines(&file.content)?
+                } else {
+                    file.content.into_bytes()
+                };
+                let comments = file.mode.map(|mode| format!("mode={:o}", mode)).into_iter().collect();
+                on_file(ArchiveFile { path: file.path, bytes, comments })?;
+            }
+        }
+        return Ok(Vec::new());
+    }
+
+    let dirs = parse_archive(content, prefix, |section| {
+        let Some((index, total)) = section.part()? else {
+            return on_file(ArchiveFile::new(section.path.clone(), decode_content(section)?, section.comments.clone())?);
+        };
+
+        let (comments, parts) = pending
+            .entry(section.path.clone())
+            .or_insert_with(|| (Vec::new(), vec![None; total]));
+        if parts.len() != total {
+            anyhow::bail!("文件 {} 的分段总数不一致", section.path);
+        }
+        if index == 1 {
+            *comments = section.comments.clone();
+        }
+        parts[index - 1] = Some(decode_content(section)?);
+
+        if parts.iter().all(Option::is_some) {
+            let (comments, parts) = pending.remove(&section.path).unwrap_or_default();
+            let bytes = parts.into_iter().flatten().flatten().collect();
+            on_file(ArchiveFile::new(section.path.clone(), bytes, comments)?)?;
+        }
+        Ok(())
+    })?;
+
+    if let Some(path) = pending.keys().next() {
+        anyhow::bail!("文件 {} 的分段不完整", path);
+    }
+    Ok(dirs)
+}
+
+// JSON 格式的打包文件以 `[` 或 `{` 开头，Markdown 格式的打包文件以文件头或说明开头（文件头前缀不能以 `[` 或 `{` 开头）
+fn is_json_archive(content: &str) -> bool {
+    matches!(content.trim_start().as_bytes().first(), Some(b'[' | b'{'))
+}
+
+// 把单独的 LF 转换成 CRLF，已经是 CRLF 的保持不变
+fn restore_crlf(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len() + bytes.len() / 16);
+    for (i, &b) in bytes.iter().enumerate() {
+        if b == b'\n' && (i == 0 || bytes[i - 1] != b'\r') {
+            result.push(b'\r');
+        }
+        result.push(b);
+    }
+    result
+}
+
+// 文件清单和空目录的标记行由文件头前缀派生，例如默认前缀 `###  trxx:` 对应 `###  trxx-manifest:`
+// 和 `###  trxx-dir:assets/empty/`
+fn marker(prefix: &str, kind: &str) -> String {
+    let base = prefix.trim_end();
+    format!("{}-{}:", base.strip_suffix(':').unwrap_or(base), kind)
+}
+
+// 解析打包文件，每解析完一个文件就调用一次 on_file，返回打包文件中记录的空目录。
+// 代码块中的内容原样属于文件正文，其中与文件头、文件清单或空目录标记相同的行不会被当成标记
+fn parse_archive(content: &str, prefix: &str, mut on_file: impl FnMut(&Section) -> Result<()>) -> Result<Vec<String>> {
+    let manifest_header = marker(prefix, "manifest");
+    let dir_header = marker(prefix, "dir");
+    let mut current: Option<Section> = None;
+    let mut dirs = Vec::new();
+    let mut is_header = true;
+    // 当前所在代码块开头的反引号数量
+    let mut fence: Option<usize> = None;
+
+    // 正文保留行尾的 \r，混用换行符的文件才能原样还原；文件头、注释和代码块边界忽略 \r
+    for raw_line in content.split_terminator('\n') {
+        let line = raw_line.strip_suffix('\r').unwrap_or(raw_line);
+        if let (Some(open), Some(section)) = (fence, current.as_mut()) {
+            // 只有不少于开头反引号数量、且只有反引号的行才能结束代码块
+            let trimmed = line.trim_end();
+            if fence_len(trimmed).is_some_and(|len| len >= open && len == trimmed.len()) {
+                fence = None;
+            } else {
+                section.body.push_str(raw_line);
+                section.body.push('\n');
+            }
+        } else if line.trim_end() == manifest_header || line.starts_with(&dir_header) {
+            // 文件清单和空目录标记不对应任何文件，一直跳到下一个文件头；多个打包文件拼接时前一个文件也在这里结束
+            if let Some(section) = current.take() {
+                if section.is_complete() {
+                    on_file(&section)?;
+                }
+            }
+            if let Some(dir) = line.strip_prefix(&dir_header) {
+                let dir = dir.trim().trim_end_matches('/');
+                if !dir.is_empty() {
+                    dirs.push(dir.to_string());
+                }
+            }
+        } else if let Some(path) = line.strip_prefix(prefix) {
+            // 保存前一个文件
+            if let Some(section) = current.take() {
+                if section.is_complete() {
+                    on_file(&section)?;
+                }
+            }
+
+            // 提取新文件名
+            current = Some(Section {
+                path: path.trim().to_string(),
+                body: String::new(),
+                is_binary: false,
+                has_body: false,
+                comments: Vec::new(),
+            });
+            is_header = true;
+            fence = None;
+        } else if let Some(section) = current.as_mut() {
+            if !is_header {
+                if let Some(len) = fence_len(line) {
+                    if line[len..].starts_with("binary") {
+                        section.is_binary = true;
+                        section.body.clear();
+                    }
+                    section.has_body = true;
+                    fence = Some(len);
+                }
+            } else if line.is_empty() {
+                is_header = false;
+            } else if let Some(comment) = line.strip_prefix("<!-- ").and_then(|l| l.strip_suffix(" -->")) {
+                match comment {
+                    "lossy-utf8" => warn!("文件 {} 打包时替换了无效的 UTF-8 字节，还原结果与原文件不一致", section.path),
+                    "whitespace-normalized" => warn!("文件 {} 打包时删除了行尾空白，还原结果与原文件不一致", section.path),
+                    "comments-stripped" => warn!("文件 {} 打包时删除了注释，还原结果与原文件不一致", section.path),
+                    _ => {}
+                }
+                section.comments.push(comment.to_string());
+            }
+        }
+    }
+
+    // 保存最后一个文件；打包文件被截断时代码块没有结束，仍然还原已有的内容
+    if let Some(mut section) = current.take() {
+        if fence.is_some() {
+            warn!("文件 {} 的代码块没有结束，打包文件可能被截断，还原的内容可能不完整", section.path);
+            section.comments.push("truncated".to_string());
+        }
+        if section.is_complete() {
+            on_file(&section)?;
+        }
+    }
+
+    Ok(dirs)
+}
+
+const CAN_SET_BTIME: bool = cfg!(any(target_os = "macos", windows));
+
+// 恢复文件的创建时间，只有 macOS 和 Windows 支持修改创建时间
+fn restore_btime(file_path: &str, btime: &str) -> Result<()> {
+    let time = humantime::parse_rfc3339(btime)
+        .with_context(|| format!("文件 {} 的创建时间 {} 无效", file_path, btime))?;
+
+    #[cfg(any(target_os = "macos", windows))]
+    {
+        #[cfg(windows)]
+        use std::os::windows::fs::FileTimesExt;
+        #[cfg(target_os = "macos")]
+        use std::os::macos::fs::FileTimesExt;
+
+        let file = OpenOptions::new()
+            .write(true)
+            .open(file_path)
+            .with_context(|| format!("无法打开文件 {}", file_path))?;
+        file.set_times(std::fs::FileTimes::new().set_created(time))
+            .with_context(|| format!("无法设置文件 {} 的创建时间", file_path))?;
+    }
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let _ = time;
+
+    Ok(())
+}
+
+// 恢复文件的修改时间，没有记录 mtime 的文件保持写入时的时间
+fn restore_mtime(file_path: &str, mtime: &str) -> Result<()> {
+    let time = humantime::parse_rfc3339(mtime)
+        .with_context(|| format!("文件 {} 的修改时间 {} 无效", file_path, mtime))?;
+    let file = OpenOptions::new()
+        .write(true)
+        .open(file_path)
+        .with_context(|| format!("无法打开文件 {}", file_path))?;
+    file.set_modified(time)
+        .with_context(|| format!("无法设置文件 {} 的修改时间", file_path))?;
+    Ok(())
+}
+
+// 恢复 tar 归档或 zip 压缩包中记录的文件权限，只有 Unix 支持
+fn restore_mode(file_path: &str, mode: &str) -> Result<()> {
+    let mode = u32::from_str_radix(mode, 8)
+        .with_context(|| format!("文件 {} 的权限 {} 无效", file_path, mode))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::set_permissions(file_path, fs::Permissions::from_mode(mode))
+            .with_context(|| format!("无法设置文件 {} 的权限", file_path))?;
+    }
+
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    Ok(())
+}
+
+fn save_content(file_path: &str, bytes: &[u8], created_dirs: &mut std::collections::HashSet<PathBuf>, opts: &RevertOptions) -> Result<()> {
+    let path = Path::new(file_path);
+    
+    // 确保父目录存在
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        let parent_path = parent.to_path_buf();
+        if opts.no_create_dirs {
+            if !parent_path.is_dir() {
+                anyhow::bail!("文件 {} 的目录 {} 不存在", file_path, parent_path.display());
+            }
+        } else if !created_dirs.contains(&parent_path) {
+            fs::create_dir_all(&parent_path)
+                .with_context(|| format!("无法创建目录 {}", parent_path.display()))?;
+            created_dirs.insert(parent_path);
+        }
+    }
+
+    fs::write(path, bytes)
+        .with_context(|| format!("无法写入文件 {}", file_path))?;
+
+    Ok(())
+}
+
+// 逐行解码 base64，不需要先把所有行拼接成一个字符串
+fn decode_base64_lines(body: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(body.len() / 4 * 3);
+    let mut pending = String::new();
+    for line in body.lines() {
+        pending.push_str(line.trim());
+        // 每 4 个字符对应 3 个字节，凑够整组再解码
+        let usable = pending.len() / 4 * 4;
+        base64::decode_config_buf(&pending[..usable], base64::STANDARD, &mut bytes)?;
+        pending.drain(..usable);
+    }
+    if !pending.is_empty() {
+        base64::decode_config_buf(&pending, base64::STANDARD, &mut bytes)?;
+    }
+    Ok(bytes)
+}
+
+// 根据文件类型还原出原始字节
+fn decode_content(section: &Section) -> Result<Vec<u8>> {
+    if section.has_comment("binary-placeholder") {
+        return Ok(Vec::new());
+    }
+    if section.is_binary {
+        let bytes = decode_base64_lines(&section.body)
+            .with_context(|| format!("无法解码文件 {}", section.path))?;
+        if section.has_comment("encoding=base64+gzip") {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(bytes.as_slice())
+                .read_to_end(&mut decompressed)
+                .with_context(|| format!("无法解压文件 {}", section.path))?;
+            return Ok(decompressed);
+        }
+        Ok(bytes)
+    } else if section.has_comment("truncated") {
+        // 被截断的代码块末尾不一定有打包时加的空行，只去掉开头的空行，不丢弃已有的内容
+        Ok(unescape_section(section, strip_leading_padding(&section.body)).into_bytes())
+    } else {
+        Ok(unescape_section(section, strip_fence_padding(&section.body)).into_bytes())
+    }
+}
+
+// 只去掉打包时在代码块首尾加的空行，文件末尾原有的换行原样保留；打包文件被转换成 CRLF 时同样适用
+fn strip_fence_padding(body: &str) -> &str {
+    let body = strip_leading_padding(body);
+    body.strip_suffix("\r\n\r\n").or_else(|| body.strip_suffix("\n\n")).unwrap_or(body)
+}
+
+fn strip_leading_padding(body: &str) -> &str {
+    body.strip_prefix("\r\n").or_else(|| body.strip_prefix('\n')).unwrap_or(body)
+}
+
+fn unescape_section(section: &Section, body: &str) -> String {
+    if section.has_comment("markdown-escaped") {
+        unescape_markdown_content(body)
+    } else {
+        body.to_string()
+    }
+}
+
+// should_process_file 不包含某个文件的原因
+fn unsupported_reason(path: &Path, opts: &PackOptions) -> SkipReason {
+    if outside_size_limits(path, opts) {
+        SkipReason::Size
+    } else {
+        SkipReason::Unsupported
+    }
+}
+
+// 文件大小超出 --max-size 或小于 --min-size，图片不受大小限制；
+// 指定了 --binary-max-size 时二进制文件（包括图片）只受它限制
+fn outside_size_limits(path: &Path, opts: &PackOptions) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+    if let Some(limit) = opts.binary_max_size {
+        if is_binary_file(path, opts) {
+            return limit > 0 && metadata.len() > limit;
+        }
+    }
+
+    let extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    if matches!(extension.as_str(), "png" | "jpg" | "jpeg" | "svg") {
+        return false;
+    }
+
+    // 默认跳过大于 1MB 的文件
+    let limit = opts.max_size.unwrap_or(DEFAULT_MAX_SIZE);
+    // 同时跳过 .gitkeep、许可证占位文件这类内容很少的小文件
+    (limit > 0 && metadata.len() > limit) || metadata.len() < opts.min_size
+}
+
+fn should_process_file(path: &Path, opts: &PackOptions) -> bool {
+    // 获取文件扩展名
+    let extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    if outside_size_limits(path, opts) {
+        return false;
+    }
+
+    // 如果是图片文件，直接返回 true
+    if matches!(extension.as_str(), "png" | "jpg" | "jpeg" | "svg") {
+        return true;
+    }
+
+    // 能从内容识别出的二进制文件（如 PDF、ICO、可执行文件）和无法识别格式但包含空字节的文件，不论扩展名都按 base64 打包
+    let denied = extension.is_empty() && opts.extensionless == Extensionless::Deny;
+    if opts.sniff && !denied && is_binary_file(path, opts) {
+        return true;
+    }
+
+    // Dockerfile、Makefile 等按文件名识别的文件不论扩展名都包含
+    if filename_language(path).is_some() {
+        return true;
+    }
+
+    // 如果没有扩展名，按 --extensionless 的设置决定是否包含
+    if extension.is_empty() {
+        return match opts.extensionless {
+            Extensionless::Allow => !has_null_bytes(path),
+            Extensionless::Deny => false,
+            Extensionless::Detect => is_probably_text(path),
+        };
+    }
+
+    // 检查是否是支持的文本文件类型，未知扩展名的文件在内容是文本时也包含
+    let known = matches!(extension.as_str(),
+        "txt" | "md" | "rs" | "js" | "ts" | "json" | "jsonc" | "json5" | "yaml" | "yml" 
+        | "toml" | "css" | "html" | "htm" | "xml" | "conf" | "cfg"
+        | "ini" | "log" | "sh" | "bash" | "py" | "java" | "cpp" | "c"
+        | "h" | "hpp" | "cs" | "go" | "rb" | "php" | "sql" | "vue"
+        | "jsx" | "tsx" | "gitignore" | "env" | "rc" | "editorconfig"
+        | "gradle" | "properties" | "bat" | "cmd" | "ps1" | "dockerfile"
+        | "lock" | "config" | "template" | "vim" | "lua" | "svg"
+        | "wxss" | "wxml" | "ux");  // 添加小程序和快应用文件类型
+    let mapped = opts.extensions.keys().any(|ext| normalize_extension(ext) == extension)
+        || opts.lang_map.as_ref().is_some_and(|map| map.contains_key(&extension));
+    known || mapped || (opts.sniff && is_probably_text(path))
+}
+
+fn is_binary_file(path: &Path, opts: &PackOptions) -> bool {
+    // 带 BOM 的 UTF-16 文本虽然包含空字节，但能转换成 UTF-8 打包
+    if opts.encoding_detect && has_utf16_bom(path) {
+        return false;
+    }
+
+    // 优先根据文件内容的魔数判断，扩展名和内容不符时以内容为准
+    if opts.sniff {
+        if let Ok(Some(kind)) = infer::get_from_path(path) {
+            return kind.matcher_type() != infer::MatcherType::Text;
+        }
+        // 无法识别格式时，前 512 字节中有空字节也按二进制处理
+        if has_null_bytes(path) {
+            return true;
+        }
+    }
+
+    let extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    matches!(extension.as_str(), "png" | "jpg" | "jpeg")
+}
+
+// 逐行扫描，遇到第一行超长的行就返回
+fn has_line_longer_than(path: &Path, limit: usize) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => return false,
+            Ok(_) => {
+                if String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).chars().count() > limit {
+                    return true;
+                }
+            }
+        }
+    }
+}
+
+// 只读取前 512 字节检查是否包含空字节
+fn has_null_bytes(path: &Path) -> bool {
+    let mut buf = [0u8; 512];
+    match File::open(path).and_then(|mut f| f.read(&mut buf)) {
+        Ok(n) => buf[..n].contains(&0),
+        Err(_) => true,
+    }
+}
+
+// 只读取前两个字节检查是否有 UTF-16 的 BOM
+fn has_utf16_bom(path: &Path) -> bool {
+    let mut buf = [0u8; 2];
+    File::open(path)
+        .and_then(|mut f| f.read_exact(&mut buf))
+        .is_ok_and(|_| utf16_bom(&buf).is_some())
+}
+
+fn is_probably_text(path: &Path) -> bool {
+    if let Ok(bytes) = fs::read(path) {
+        // 检查文件是否为有效的 UTF-8
+        if String::from_utf8(bytes.clone()).is_ok() {
+            // 检查前512字节是否包含空字节
+            for &byte in bytes.iter().take(512) {
+                if byte == 0 {
+                    return false;
+                }
+            }
+            return true;
+        }
+    }
+    false
+} 
@@ -1,12 +1,16 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use clap::{Parser, Subcommand};
-use std::fs::{self, File};
-use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
+use clap::ArgAction;
 use std::collections::HashMap;
-use glob::glob;
-use serde_json;
-use base64;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::Level;
+use trxx::{
+    dump_extension_map, list_files, load_lang_map, pack_files, parse_size, preview_revert, print_stats, revert_files, verify_archive, watch,
+    Config, DEFAULT_HEADER_PREFIX, EolMode, Extensionless, InvalidUtf8, LockMode, LockOptions, OutputCompression, OutputFormat, PackOptions,
+    RelpathStyle, RevertOptions, SortOrder,
+};
 
 // 我来解释一下 #[command(subcommand)] 这个属性标注的含义：
 
@@ -51,19 +55,399 @@ struct Cli {
     /// 目录路径，用于打包文件
     #[arg(default_value = ".")]
     path: Option<String>,
+
+    /// 输出文件被其他进程锁定时的处理方式
+    #[arg(long, value_enum, default_value_t = LockMode::Wait)]
+    lock_mode: LockMode,
+
+    /// 等待输出文件锁的超时时间（秒）
+    #[arg(long, default_value_t = 30)]
+    lock_timeout: u64,
+
+    /// 文件头中路径的表示方式
+    #[arg(long, value_enum, default_value_t = RelpathStyle::Relative)]
+    relpath_style: RelpathStyle,
+
+    /// 在文件头的路径前加上目录前缀（如 backend），还原时文件会写到该目录下；多个仓库打包到同一个文件时避免路径冲突
+    #[arg(long, value_name = "STR", value_parser = parse_root_prefix)]
+    root_prefix: Option<String>,
+
+    /// 报告同时包含 LF 和 CRLF 换行符的文件（不修改文件内容）
+    #[arg(long)]
+    warn_mixed_eol: bool,
+
+    /// 换行符处理方式：keep（默认，还原后保持原样）、lf、crlf
+    #[arg(long, value_enum, default_value_t = EolMode::Keep)]
+    normalize_eol: EolMode,
+
+    /// 同时将打包结果复制到系统剪贴板（需要启用 clipboard 功能编译），超过 1MB 时提醒；与 --stdout 同时使用时两处都会输出，不写入输出文件
+    #[arg(long)]
+    clipboard: bool,
+
+    /// 在图片的文件头中记录格式和尺寸
+    #[arg(long)]
+    include_binary_metadata: bool,
+
+    /// 无扩展名文件的处理方式
+    #[arg(long, value_enum, default_value_t = Extensionless::Detect)]
+    extensionless: Extensionless,
+
+    /// 跳过包含超过 N 个字符的行的文件（如压缩后的脚本、内嵌数据）
+    #[arg(long, value_name = "N")]
+    max_line_length: Option<usize>,
+
+    /// 遇到不是有效 UTF-8 编码的文本文件时的处理方式
+    #[arg(long, value_enum, default_value_t = InvalidUtf8::Error)]
+    on_invalid_utf8: InvalidUtf8,
+
+    /// 将每个目录下的 README.* 排在该目录其他文件之前
+    #[arg(long)]
+    readme_first: bool,
+
+    /// 二进制文件先用 gzip 压缩再 base64 编码（仅在压缩后更小时生效）
+    #[arg(long)]
+    compress_binary: bool,
+
+    /// 将超过指定字节数的单个文件拆分成多段，还原时自动拼接
+    #[arg(long, value_name = "BYTES")]
+    split_file_over: Option<usize>,
+
+    /// 打包后分析已包含的文件，在标准错误输出中给出建议忽略的模式
+    #[arg(long)]
+    suggest_ignores: bool,
+
+    /// 删除文本文件每行末尾的空白字符（会改变文件内容，还原后与原文件不一致）
+    #[arg(long)]
+    normalize_whitespace: bool,
+
+    /// 在文件头中记录文件创建时间（btime），平台不支持时跳过
+    #[arg(long)]
+    record_ctime: bool,
+
+    /// 只打包提交作者匹配该模式的文件（传给 git log --author，不在 git 中的文件会被排除）
+    #[arg(long, value_name = "PATTERN")]
+    git_author: Option<String>,
+
+    /// 输出更详细的日志，-v 显示每个文件的处理情况，-vv 显示全部调试信息
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// 以 JSON 格式输出日志
+    #[arg(long, global = true)]
+    log_json: bool,
+
+    /// 文件头的前缀，内容中可能出现默认前缀时换成不会冲突的字符串；打包和还原需要使用相同的前缀；不能以 [ 或 { 开头，空目录和文件清单的标记也由它派生
+    #[arg(long, global = true, value_name = "STR", default_value = DEFAULT_HEADER_PREFIX, value_parser = parse_header_prefix)]
+    header_prefix: String,
+
+    /// 不根据文件内容判断类型，只按扩展名识别二进制文件和文本文件
+    #[arg(long)]
+    no_sniff: bool,
+
+    /// 每种语言最多包含 N 个文件，多出的文件按排序顺序丢弃
+    #[arg(long, value_name = "N")]
+    limit_per_language: Option<usize>,
+
+    /// 只打包指定语言的文件（语言名见 dump-ext-map 的输出，如 rust、typescript，可重复使用）
+    #[arg(long, value_name = "LANG")]
+    lang_only: Vec<String>,
+
+    /// 在代码块第一行插入带文件路径的注释（按语言选择注释语法），还原时会自动去掉
+    #[arg(long)]
+    path_comments: bool,
+
+    /// 在每个文本文件的文件头中注明行数和字符数，例如 `<!-- 123 lines, 4567 chars -->`，还原时忽略
+    #[arg(long)]
+    annotate: bool,
+
+    /// 在文本文件的每一行前加上右对齐的行号（如 ` 12 | `），只作用于 markdown 输出；还原时按文件头中的 line-numbers 标记去掉，手动编辑过行号前缀的文件无法逐字节还原
+    #[arg(long)]
+    line_numbers: bool,
+
+    /// 只统计文件数量、大小、语言分布和估算的 token 数，不生成打包文件
+    #[arg(long)]
+    estimate: bool,
+
+    /// 包含 *.lock 锁文件（默认忽略）
+    #[arg(long)]
+    include_lockfiles: bool,
+
+    /// 包含与输出文件同名的文件（默认忽略，避免把之前的打包结果再打包进去）
+    #[arg(long)]
+    include_output: bool,
+
+    /// 将打包结果写入所在 git 仓库根目录下的 .trxx/ 目录，而不是当前目录；同时指定 -o 相对路径时相对于仓库根目录
+    #[arg(long)]
+    output_repo_root: bool,
+
+    /// 输出文件路径，默认为当前目录下的 all_content.md，父目录不存在时会自动创建
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// 文件排序方式，默认按相对路径的字典序排序
+    #[arg(long, value_enum, default_value_t = SortOrder::Path)]
+    sort: SortOrder,
+
+    /// 在打包文件开头写入元信息头（生成时间、git 提交信息）
+    #[arg(long)]
+    header: bool,
+
+    /// 确定性输出：元信息头中不写入生成时间等每次运行都会变化的内容
+    #[arg(long)]
+    deterministic: bool,
+
+    /// 排除测试文件（如 *_test.rs、*.test.js、test_*.py、tests/ 目录下的文件）
+    #[arg(long, conflicts_with = "tests_only")]
+    exclude_tests: bool,
+
+    /// 只打包测试文件
+    #[arg(long)]
+    tests_only: bool,
+
+    /// 跳过所有二进制文件（包括图片），只打包文本文件
+    #[arg(long, conflicts_with = "binary_only")]
+    no_binary: bool,
+
+    /// 只打包二进制文件（包括图片）
+    #[arg(long)]
+    binary_only: bool,
+
+    /// 额外的测试文件模式（相对于打包目录的 glob，可重复使用）
+    #[arg(long, value_name = "GLOB")]
+    test_pattern: Vec<String>,
+
+    /// 安静模式，不输出提示信息和打包摘要，只输出警告和错误
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// 二进制文件只写入文件头和一行占位说明，不写入 base64 内容（还原时无法恢复这些文件）
+    #[arg(long)]
+    binary_placeholder: bool,
+
+    /// 打包结束后按原因分类输出被跳过的文件数量（使用 -v 时也会输出）
+    #[arg(long)]
+    stats: bool,
+
+    /// 在跳过统计中列出每个被跳过的文件
+    #[arg(long)]
+    list_skipped: bool,
+
+    /// 不读取 .gitignore，只使用内置的忽略规则
+    #[arg(long)]
+    no_gitignore: bool,
+
+    /// 并行读取和处理文件的线程数，默认为逻辑 CPU 数
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// 将打包结果写到标准输出，不生成文件
+    #[arg(long, conflicts_with_all = ["output", "output_repo_root"])]
+    stdout: bool,
+
+    /// 排除匹配的文件或目录（相对于打包目录的 glob，可重复使用），在内置忽略规则之外生效
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// 只打包匹配的文件或目录（相对于打包目录的 glob，可重复使用）；同时匹配 --exclude 和忽略规则的文件仍会被排除
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// 配置文件路径，默认读取打包目录下的 .trxx.toml；命令行参数优先于配置文件
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// 文本文件的大小上限，支持 K、M、G 后缀，0 表示不限制；默认 1M（SVG 不受默认上限限制）
+    #[arg(long, value_name = "BYTES", value_parser = parse_size)]
+    max_size: Option<u64>,
+
+    /// 跳过小于该大小的文件（图片除外），支持 K、M、G 后缀；默认 0，不跳过
+    #[arg(long, value_name = "BYTES", value_parser = parse_size, default_value = "0")]
+    min_size: u64,
+
+    /// 二进制文件（包括图片）的大小上限，支持 K、M、G 后缀，0 表示不限制；指定后 --max-size 只对文本文件生效
+    #[arg(long, value_name = "BYTES", value_parser = parse_size)]
+    binary_max_size: Option<u64>,
+
+    /// 最大目录深度（相对于打包目录），0 表示只打包根目录下的文件
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// 同时打包以点开头的文件和目录（如 .env、.github/），默认跳过；.git 等目录仍然忽略
+    #[arg(long)]
+    hidden: bool,
+
+    /// 在文件头中记录文件大小和修改时间，例如 `<!-- size=1234 mtime=2024-01-02T03:04:05Z -->`
+    #[arg(long)]
+    metadata: bool,
+
+    /// 在文件头中记录每个文件的 SHA-256 校验和，还原时校验内容是否完整
+    #[arg(long)]
+    checksum: bool,
+
+    /// 不检测非 UTF-8 文本的编码，只按 --on-invalid-utf8 处理
+    #[arg(long)]
+    no_encoding_detect: bool,
+
+    /// 压缩输出文件：none（默认）、gzip（输出文件名追加 .gz，还原时自动解压）
+    #[arg(long, value_enum, default_value_t = OutputCompression::None)]
+    compress: OutputCompression,
+
+    /// 输出格式：markdown（默认）、json（每个文件一个 path、language、binary、content 对象）、tar（原样保存内容和权限，默认输出 all_content.tar）、zip（默认输出 all_content.zip）；还原时自动识别
+    #[arg(long, value_enum, default_value_t = OutputFormat::Markdown, conflicts_with_all = ["split_size", "split_tokens"])]
+    format: OutputFormat,
+
+    /// 在打包文件开头写入文件清单（路径、大小、语言和总计），还原时会被跳过
+    #[arg(long)]
+    manifest: bool,
+
+    /// 打包后在标准错误中输出估算的 token 总数和按语言的分布
+    #[arg(long)]
+    tokens: bool,
+
+    /// 估算的 token 总数超过该值时给出警告
+    #[arg(long, value_name = "N")]
+    token_limit: Option<usize>,
+
+    /// 按字节数拆分输出为 all_content.001.md、all_content.002.md……，只在文件之间断开
+    #[arg(long, value_name = "BYTES", value_parser = parse_size, conflicts_with_all = ["stdout", "split_tokens"])]
+    split_size: Option<u64>,
+
+    /// 按估算的 token 数拆分输出，只在文件之间断开
+    #[arg(long, value_name = "N", conflicts_with = "stdout")]
+    split_tokens: Option<usize>,
+
+    /// 试运行：照常读取和处理所有文件，但不写入输出，只在标准错误中报告将要写入的路径、大小和文件数
+    #[arg(long)]
+    dry_run: bool,
+
+    /// 单个文件读取或解码失败时跳过该文件继续打包，最后汇总失败的文件并以非零状态退出
+    #[arg(long)]
+    keep_going: bool,
+
+    /// 追加扩展名到语言的映射，格式为 扩展名=语言，可以重复使用；优先于内置映射和配置文件
+    #[arg(long, value_name = "EXT=LANG", value_parser = parse_lang_mapping)]
+    lang: Vec<(String, String)>,
+
+    /// 从 JSON 文件读取扩展名到语言的映射（格式与 dump-ext-map 的输出相同），替换内置映射
+    #[arg(long, value_name = "PATH")]
+    lang_map: Option<PathBuf>,
+
+    /// 将 --lang-map 的映射合并到内置映射中，而不是替换
+    #[arg(long, requires = "lang_map")]
+    merge_lang_map: bool,
+
+    /// 不转义 markdown 文件中以 ``` 或 # 开头的行，原样写入内容
+    #[arg(long)]
+    no_escape: bool,
+
+    /// 记录空目录，还原时重新创建
+    #[arg(long)]
+    keep_empty_dirs: bool,
+
+    /// 只打包文件列表中的路径（每行一个，相对于打包目录），按列表顺序打包；`-` 表示从标准输入读取
+    #[arg(long, value_name = "PATH")]
+    files_from: Option<PathBuf>,
+
+    /// 在标准错误中显示进度条：已处理的文件数和当前文件
+    #[arg(long)]
+    progress: bool,
+
+    /// 增量打包：只包含与指定打包文件相比新增或有变化的文件（之前打包时使用 --metadata 可以跳过读取未变化的文件）
+    #[arg(long, value_name = "BUNDLE")]
+    since: Option<PathBuf>,
+
+    /// 内容完全相同的文件只保留第一个，后面的只记录原文件路径，还原时复制原文件的内容（只对 markdown 格式生效）
+    #[arg(long)]
+    dedup: bool,
+
+    /// 打包前在终端中勾选要打包的文件和目录（默认全部选中）；不在交互式终端中时打包所有文件
+    #[arg(long)]
+    interactive: bool,
+
+    /// 删除源代码中的注释以节省 token（支持 Rust、C/C++、Java、JS/TS、Go、Python、Shell、YAML、TOML、SQL、HTML 等；会改变文件内容，还原后与原文件不一致）
+    #[arg(long)]
+    strip_comments: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// 还原文件
     Revert {
-        /// 输入文件路径
-        input: String,
+        /// 输入文件路径，可以指定多个，按顺序还原到同一个目录；`-` 表示从标准输入读取，目录和 glob 模式按自然顺序展开
+        #[arg(required = true)]
+        input: Vec<String>,
+
+        /// 根据文件头中的 btime 恢复文件创建时间（仅 macOS 和 Windows 支持）
+        #[arg(long)]
+        preserve_ctime: bool,
+
+        /// 还原到指定目录（不存在时自动创建），默认为当前目录
+        #[arg(long, value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+
+        /// 允许还原绝对路径的文件头，写回原来的位置（只应对可信的打包文件使用）
+        #[arg(long, conflicts_with = "output_dir")]
+        allow_absolute: bool,
+
+        /// 根据文件头中的 mtime 恢复文件修改时间（需要打包时使用 --metadata）
+        #[arg(long)]
+        preserve_mtime: bool,
+
+        /// 不自动创建缺失的目录，目标文件的父目录不存在时报错
+        #[arg(long)]
+        no_create_dirs: bool,
+
+        /// 严格模式：遇到无法还原的文件（如二进制占位）时报错，而不是跳过
+        #[arg(long)]
+        strict: bool,
+
+        /// 只还原路径匹配的文件，支持 glob 模式（如 "src/**/*.rs"），匹配目录时还原其中的所有文件；可以重复使用
+        #[arg(long, value_name = "PATTERN")]
+        only: Vec<String>,
+
+        /// 只列出每个文件会被新建、覆盖还是保持不变，不写入任何文件；有文件会被覆盖时以非零状态退出
+        #[arg(long)]
+        verify_only: bool,
+    },
+    /// 不写入任何文件，检查打包文件能否完整还原出目录中的文件；有不同、缺失或多余的文件时以非零状态退出。
+    /// 打包时使用的选项（如 --root-prefix、--exclude）写在 verify 之前，例如 `trxx --root-prefix backend verify bundle.md --dir backend`
+    Verify {
+        /// 打包文件路径，可以指定多个；`-` 表示从标准输入读取，目录和 glob 模式按自然顺序展开
+        #[arg(required = true)]
+        input: Vec<String>,
+
+        /// 用于比较的目录
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+    },
+    /// 列出会被打包的文件、大小和语言，不生成打包文件；打包选项写在 list 之前，例如 `trxx --exclude docs list .`
+    List {
+        /// 要检查的目录，默认为当前目录
+        path: Option<String>,
+    },
+    /// 统计会被打包的文件：总数、总大小、各语言的文件数和大小、二进制文件数以及因大小限制跳过的文件数；不写入打包文件
+    Stats {
+        /// 要统计的目录，默认为当前目录
+        path: Option<String>,
+        /// 以 JSON 格式输出
+        #[arg(long)]
+        json: bool,
+    },
+    /// 先打包一次，之后监视目录，文件变化时自动重新打包；打包选项写在 watch 之前，例如 `trxx -o docs/bundle.md watch .`
+    Watch {
+        /// 要监视的目录，默认为当前目录
+        path: Option<String>,
+    },
+    /// 输出内置的扩展名到语言映射表（JSON），可作为自定义映射的模板
+    DumpExtMap {
+        /// 输出文件路径，不指定时输出到标准输出
+        output: Option<String>,
     },
 }
 
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    init_logging(cli.verbose, cli.quiet, cli.log_json);
 
     // ```
     // Commands::Revert { input } 是什么语法？
@@ -145,348 +529,205 @@ fn main() -> Result<()> {
     // }
     // ```
 
-    match cli.command {
-        Some(Commands::Revert { input }) => revert_files(&input),
-        None => {
-            let path = cli.path.unwrap_or_else(|| ".".to_string());
-            pack_files(&path)
-        }
-    }
-}
-
-fn should_ignore_path(path: &Path) -> bool {
-    let path_str = path.to_string_lossy();
-    
-    // 检查是否包含需要忽略的目录
-    if path_str.contains("/.git/") || 
-       path_str.contains("/target/") || 
-       path_str.contains("/node_modules/") {
-        eprintln!("忽略路径: {}", path.display());
-        return true;
-    }
-
-    // 检查是否是需要忽略的文件
-    if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-        if file_name == "all_content.md" || file_name.ends_with(".lock") {
-            return true;
-        }
-    }
-
-    false
-}
-
-fn load_extension_map() -> Result<HashMap<String, String>> {
-    let map_content = r#"{
-        "rs": "rust",
-        "json": "json",
-        "js": "javascript",
-        "ts": "typescript",
-        "py": "python",
-        "java": "java",
-        "cpp": "cpp",
-        "c": "c",
-        "go": "go",
-        "rb": "ruby",
-        "php": "php",
-        "html": "html",
-        "css": "css",
-        "md": "markdown",
-        "yaml": "yaml",
-        "yml": "yaml",
-        "toml": "toml",
-        "sh": "bash",
-        "bash": "bash",
-        "sql": "sql",
-        "vue": "vue",
-        "jsx": "jsx",
-        "tsx": "tsx",
-        "lua": "lua",
-        ".h": "c/c++ header",
-        ".conf": "conf",
-        ".ini": "ini",
-        ".txt": "text",
-        ".bat": "batch file",
-        ".ps1": "powershell",
-        ".env": "env",
-        ".gitignore": "gitignore",
-        "wxss": "css",
-        "wxml": "xml",
-        "ux": "html"
-    }"#;
-    
-    let map: HashMap<String, String> = serde_json::from_str(map_content)?;
-    Ok(map)
-}
-
-fn escape_markdown_content(content: &str, is_markdown: bool) -> String {
-    if !is_markdown {
-        return content.to_string();
-    }
-
-    content.lines()
-        .map(|line| {
-            if line.starts_with("```") {
-                format!("\\{}", line)
-            } else if line.starts_with('#') {
-                format!("\\{}", line)
-            } else {
-                line.to_string()
-            }
-        })
-        .collect::<Vec<String>>()
-        .join("\n")
-}
-
-fn collect_files(dir_path: &Path) -> Result<Vec<PathBuf>> {
-    let pattern = format!("{}/**/*", dir_path.display());
-    let mut files = Vec::new();
-    
-    for entry in glob(&pattern)? {
-        if let Ok(path) = entry {
-            if path.is_file() && !should_ignore_path(&path) && should_process_file(&path) {
-                files.push(path);
+    match cli.command.take() {
+        Some(Commands::Revert { input, verify_only, preserve_ctime, preserve_mtime, output_dir, allow_absolute, no_create_dirs, strict, only }) => {
+            let opts = RevertOptions {
+                preserve_ctime,
+                preserve_mtime,
+                output_dir,
+                no_create_dirs,
+                strict,
+                allow_absolute,
+                only,
+                header_prefix: Some(cli.header_prefix.clone()),
+            };
+            if verify_only {
+                if preview_revert(&input, &opts)? {
+                    std::process::exit(1);
+                }
+                return Ok(());
             }
+            revert_files(&input, &opts)
         }
-    }
-    
-    Ok(files)
-}
-
-fn pack_files(dir_path: &str) -> Result<()> {
-    let extension_map = load_extension_map()?;
-    let abs_path = fs::canonicalize(dir_path)?;
-    let mut all_content = String::new();
-    
-    // 先收集所有符合条件的文件
-    let files = collect_files(&abs_path)?;
-    
-    if files.is_empty() {
-        println!("没有找到任何有效的文本文件");
-        return Ok(());
-    }
-
-    // 处理每个文件
-    for path in files {
-        let rel_path = path.strip_prefix(&abs_path)?.to_string_lossy().to_string();
-        
-        // 检查是否是 markdown 文件
-        let is_markdown = path.extension()
-            .and_then(|e| e.to_str())
-            .map(|e| e.to_lowercase())
-            .map(|ext| ext == "md")
-            .unwrap_or(false);
-        
-        // 读取并处理文件内容
-        let content = process_file(&path, &rel_path, &extension_map, is_markdown)?;
-        all_content.push_str(&content);
-    }
-
-    fs::write("all_content.md", all_content)?;
-    println!("文件已打包到 all_content.md");
-    Ok(())
-}
-
-fn process_file(path: &Path, rel_path: &str, extension_map: &HashMap<String, String>, is_markdown: bool) -> Result<String> {
-    let mut result = String::new();
-    
-    // 添加文件头
-    result.push_str(&format!("###  trxx:{}\n\n", rel_path));
-    
-    if is_binary_file(path) {
-        // 处理二进制文件（图片）
-        let bytes = fs::read(path)?;
-        let base64 = base64::encode(&bytes);
-        
-        result.push_str("```binary\n");
-        result.push_str(&base64);
-        result.push_str("\n```\n\n");
-    } else {
-        // 处理文本文件
-        let bytes = fs::read(path)?;
-        let content = String::from_utf8(bytes)
-            .with_context(|| format!("文件 {} 不是有效的 UTF-8 编码", rel_path))?;
-        
-        // 添加语言标识符
-        if let Some(ext) = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()) {
-            if let Some(lang) = extension_map.get(&ext) {
-                result.push_str(&format!("```{}", lang));
-            } else {
-                result.push_str("```");
+        Some(Commands::Verify { input, dir }) => {
+            let opts = pack_options(cli, &dir.to_string_lossy())?;
+            if !verify_archive(&input, &dir, &opts)? {
+                std::process::exit(1);
             }
-        } else {
-            result.push_str("```");
+            Ok(())
+        }
+        Some(Commands::DumpExtMap { output }) => dump_extension_map(output.as_deref()),
+        Some(Commands::List { path }) => {
+            let path = path.unwrap_or_else(|| ".".to_string());
+            let opts = pack_options(cli, &path)?;
+            list_files(&path, &opts)
+        }
+        Some(Commands::Stats { path, json }) => {
+            let path = path.unwrap_or_else(|| ".".to_string());
+            let opts = pack_options(cli, &path)?;
+            print_stats(&path, &opts, json)
+        }
+        Some(Commands::Watch { path }) => {
+            let path = path.unwrap_or_else(|| ".".to_string());
+            let opts = pack_options(cli, &path)?;
+            watch(&path, &opts)
+        }
+        None => {
+            let path = cli.path.clone().unwrap_or_else(|| ".".to_string());
+            let opts = pack_options(cli, &path)?;
+            pack_files(&path, &opts)
         }
-        result.push_str("\n\n");
-        
-        // 处理内容
-        let processed_content = escape_markdown_content(&content, is_markdown);
-        result.push_str(&processed_content);
-        result.push_str("\n\n");
-        result.push_str("```");
-        result.push_str("\n\n");
     }
-    
-    Ok(result)
 }
 
-fn unescape_markdown_content(line: &str, is_markdown: bool) -> String {
-    if !is_markdown {
-        return line.to_string();
-    }
-
-    if line.starts_with("\\```") {
-        line.trim_start_matches('\\').to_string()
-    } else if line.starts_with("\\#") {
-        line.trim_start_matches('\\').to_string()
+// 合并命令行参数和配置文件，得到打包选项
+fn pack_options(cli: Cli, path: &str) -> Result<PackOptions> {
+    let config = Config::load(Path::new(path), cli.config.as_deref())?;
+    // 命令行指定了其他输出方式时忽略配置文件中的输出路径
+    let output = if cli.stdout {
+        None
     } else {
-        line.to_string()
-    }
+        cli.output.or(config.output)
+    };
+    // 合并时外部映射优先于内置映射，配置文件和 --lang 又优先于外部映射
+    let (lang_map, mut extensions) = match &cli.lang_map {
+        Some(path) if cli.merge_lang_map => (None, load_lang_map(path)?),
+        Some(path) => (Some(load_lang_map(path)?), HashMap::new()),
+        None => (None, HashMap::new()),
+    };
+    extensions.extend(config.extensions);
+    extensions.extend(cli.lang);
+    let max_size = match (cli.max_size, config.max_size) {
+        (Some(size), _) => Some(size),
+        (None, Some(size)) => Some(parse_size(&size).map_err(anyhow::Error::msg)?),
+        (None, None) => None,
+    };
+    Ok(PackOptions {
+        lock: LockOptions {
+            mode: cli.lock_mode,
+            timeout: Duration::from_secs(cli.lock_timeout),
+        },
+        relpath_style: cli.relpath_style,
+        root_prefix: cli.root_prefix,
+        warn_mixed_eol: cli.warn_mixed_eol,
+        clipboard: cli.clipboard,
+        include_binary_metadata: cli.include_binary_metadata,
+        extensionless: cli.extensionless,
+        max_line_length: cli.max_line_length,
+        on_invalid_utf8: cli.on_invalid_utf8,
+        readme_first: cli.readme_first,
+        compress_binary: cli.compress_binary,
+        split_file_over: cli.split_file_over,
+        suggest_ignores: cli.suggest_ignores,
+        normalize_whitespace: cli.normalize_whitespace,
+        record_ctime: cli.record_ctime,
+        git_author: cli.git_author,
+        sniff: !cli.no_sniff,
+        limit_per_language: cli.limit_per_language,
+        lang_only: cli.lang_only,
+        path_comments: cli.path_comments,
+        annotate: cli.annotate,
+        line_numbers: cli.line_numbers,
+        estimate: cli.estimate,
+        include_lockfiles: cli.include_lockfiles,
+        include_output: cli.include_output,
+        output_repo_root: cli.output_repo_root,
+        sort: cli.sort,
+        header: cli.header,
+        deterministic: cli.deterministic,
+        exclude_tests: cli.exclude_tests,
+        tests_only: cli.tests_only,
+        no_binary: cli.no_binary,
+        binary_only: cli.binary_only,
+        test_patterns: cli.test_pattern,
+        binary_placeholder: cli.binary_placeholder,
+        skip_report: cli.stats || cli.verbose > 0,
+        list_skipped: cli.list_skipped,
+        gitignore: !cli.no_gitignore,
+        output,
+        jobs: cli.jobs.or(config.jobs),
+        stdout: cli.stdout,
+        exclude: if cli.exclude.is_empty() { config.exclude } else { cli.exclude },
+        include: if cli.include.is_empty() { config.include } else { cli.include },
+        extensions,
+        max_size,
+        min_size: cli.min_size,
+        binary_max_size: cli.binary_max_size,
+        max_depth: cli.max_depth,
+        hidden: cli.hidden,
+        metadata: cli.metadata,
+        checksum: cli.checksum,
+        encoding_detect: !cli.no_encoding_detect,
+        normalize_eol: cli.normalize_eol,
+        compress: cli.compress,
+        format: cli.format,
+        manifest: cli.manifest,
+        tokens: cli.tokens,
+        token_limit: cli.token_limit,
+        split_size: cli.split_size,
+        split_tokens: cli.split_tokens,
+        dry_run: cli.dry_run,
+        keep_going: cli.keep_going,
+        lang_map,
+        escape_markdown: !cli.no_escape,
+        keep_empty_dirs: cli.keep_empty_dirs,
+        files_from: cli.files_from,
+        progress: cli.progress,
+        since: cli.since,
+        dedup: cli.dedup,
+        interactive: cli.interactive,
+        strip_comments: cli.strip_comments,
+        header_prefix: cli.header_prefix,
+    })
 }
 
-fn revert_files(input_path: &str) -> Result<()> {
-    let content = fs::read_to_string(input_path)
-        .with_context(|| format!("无法读取文件 {}", input_path))?;
-
-    let mut current_file = String::new();
-    let mut current_content = String::new();
-    let mut is_header = true;
-    let mut in_code_block = false;
-    let mut is_binary = false;
-
-    // 创建一个 Set 来记录已创建的目录
-    let mut created_dirs = std::collections::HashSet::new();
-
-    for line in content.lines() {
-        if line.starts_with("###  trxx:") {
-            // 保存前一个文件
-            if !current_file.is_empty() && !current_content.is_empty() {
-                save_content(&current_file, &current_content, is_binary, &mut created_dirs)?;
-            }
-
-            // 提取新文件名
-            current_file = line
-                .trim_start_matches("###  trxx:")
-                .trim()
-                .to_string();
-            
-            current_content = String::new();
-            is_header = true;
-            in_code_block = false;
-            is_binary = false;
-        } else if !is_header {
-            if line.starts_with("```binary") {
-                in_code_block = true;
-                is_binary = true;
-                current_content.clear();
-                continue;
-            } else if line.starts_with("```") {
-                in_code_block = !in_code_block;
-                continue;
-            }
-            
-            if in_code_block {
-                current_content.push_str(line);
-                current_content.push('\n');
-            }
-        } else if line.is_empty() {
-            is_header = false;
+// 解析 --lang 的 `扩展名=语言`
+fn parse_lang_mapping(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((ext, lang)) if !ext.trim().is_empty() && !lang.trim().is_empty() => {
+            Ok((ext.trim().to_string(), lang.trim().to_string()))
         }
+        _ => Err(format!("无效的映射 {}，格式应为 扩展名=语言，例如 zig=zig", value)),
     }
-
-    // 保存最后一个文件
-    if !current_file.is_empty() && !current_content.is_empty() {
-        save_content(&current_file, &current_content, is_binary, &mut created_dirs)?;
-    }
-
-    println!("文件已还原完成");
-    Ok(())
 }
 
-fn save_content(file_path: &str, content: &str, is_binary: bool, created_dirs: &mut std::collections::HashSet<PathBuf>) -> Result<()> {
-    let path = Path::new(file_path);
-    
-    // 确保父目录存在
-    if let Some(parent) = path.parent() {
-        let parent_path = parent.to_path_buf();
-        if !created_dirs.contains(&parent_path) {
-            fs::create_dir_all(&parent_path)
-                .with_context(|| format!("无法创建目录 {}", parent_path.display()))?;
-            created_dirs.insert(parent_path);
-        }
+// 解析 --header-prefix，前缀会原样出现在每个文件头的开头
+fn parse_header_prefix(value: &str) -> Result<String, String> {
+    if value.trim().is_empty() || value.contains(['\n', '\r']) {
+        return Err("文件头前缀不能为空，也不能包含换行".to_string());
     }
-
-    // 根据文件类型保存内容
-    if is_binary {
-        let bytes = base64::decode(content.trim())
-            .with_context(|| format!("无法解码文件 {}", file_path))?;
-        fs::write(path, bytes)
-            .with_context(|| format!("无法写入文件 {}", file_path))?;
-    } else {
-        let trimmed_content = content.trim_matches('\n');
-        fs::write(path, trimmed_content)
-            .with_context(|| format!("无法写入文件 {}", file_path))?;
+    // 以 [ 或 { 开头的打包文件会被当成 JSON 格式
+    if value.trim_start().starts_with(['[', '{']) {
+        return Err("文件头前缀不能以 [ 或 { 开头".to_string());
     }
-
-    Ok(())
+    Ok(value.to_string())
 }
 
-fn should_process_file(path: &Path) -> bool {
-    // 获取文件扩展名
-    let extension = path.extension()
-        .and_then(|ext| ext.to_str())
-        .map(|e| e.to_lowercase())
-        .unwrap_or_default();
-
-    // 如果是图片文件，直接返回 true
-    if matches!(extension.as_str(), "png" | "jpg" | "jpeg" | "svg") {
-        return true;
-    }
-
-    // 如果文件大于 1MB，且不是 SVG，则跳过
-    if let Ok(metadata) = path.metadata() {
-        if metadata.len() > 1024 * 1024 && extension != "svg" {
-            return false;
-        }
-    }
-
-    // 如果没有扩展名，尝试检测是否为文本文件
-    if extension.is_empty() {
-        return is_probably_text(path);
+// 解析 --root-prefix，去掉首尾的 /，不允许 . 和 .. 这类会离开还原目录的路径
+fn parse_root_prefix(value: &str) -> Result<String, String> {
+    let prefix = value.trim_matches('/');
+    if prefix.is_empty() || prefix.contains(['\n', '\r']) || prefix.split('/').any(|c| c.is_empty() || c == "." || c == "..") {
+        return Err(format!("无效的路径前缀 {}，应为相对目录路径，例如 backend 或 repos/api", value));
     }
-
-    // 检查是否是支持的文本文件类型
-    matches!(extension.as_str(),
-        "txt" | "md" | "rs" | "js" | "ts" | "json" | "yaml" | "yml" 
-        | "toml" | "css" | "html" | "htm" | "xml" | "conf" | "cfg"
-        | "ini" | "log" | "sh" | "bash" | "py" | "java" | "cpp" | "c"
-        | "h" | "hpp" | "cs" | "go" | "rb" | "php" | "sql" | "vue"
-        | "jsx" | "tsx" | "gitignore" | "env" | "rc" | "editorconfig"
-        | "gradle" | "properties" | "bat" | "cmd" | "ps1" | "dockerfile"
-        | "lock" | "config" | "template" | "vim" | "lua" | "svg"
-        | "wxss" | "wxml" | "ux")  // 添加小程序和快应用文件类型
-}
-
-fn is_binary_file(path: &Path) -> bool {
-    let extension = path.extension()
-        .and_then(|ext| ext.to_str())
-        .map(|e| e.to_lowercase())
-        .unwrap_or_default();
-
-    matches!(extension.as_str(), "png" | "jpg" | "jpeg")
+    Ok(prefix.to_string())
 }
 
-fn is_probably_text(path: &Path) -> bool {
-    if let Ok(bytes) = fs::read(path) {
-        // 检查文件是否为有效的 UTF-8
-        if String::from_utf8(bytes.clone()).is_ok() {
-            // 检查前512字节是否包含空字节
-            for &byte in bytes.iter().take(512) {
-                if byte == 0 {
-                    return false;
-                }
-            }
-            return true;
-        }
+// 日志统一输出到标准错误，标准输出只留给命令本身的输出
+fn init_logging(verbose: u8, quiet: bool, json: bool) {
+    let level = match verbose {
+        _ if quiet => Level::WARN,
+        0 => Level::INFO,
+        1 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_target(false);
+    if json {
+        builder.json().init();
+    } else {
+        builder.without_time().init();
     }
-    false
-} 
+}
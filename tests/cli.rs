@@ -0,0 +1,366 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn header_prefix_cannot_look_like_json() {
+    let dir = tree(&[("a.txt", b"a\n")]);
+    for prefix in ["[file] ", " {file}"] {
+        let output = trxx(&["--header-prefix", prefix, "--stdout", "."], dir.path());
+        assert!(!output.status.success());
+        assert!(stderr(&output).contains("不能以 [ 或 { 开头"), "{}", stderr(&output));
+    }
+}
+
+#[test]
+fn output_repo_root_composes_with_output() {
+    let dir = tree(&[("sub/a.txt", b"a\n")]);
+    std::fs::create_dir(dir.path().join(".git")).unwrap();
+    let sub = dir.path().join("sub");
+
+    let output = trxx(&["-q", "--output-repo-root", "-o", "bundles/a.md", "."], &sub);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(dir.path().join("bundles/a.md").is_file());
+
+    let output = trxx(&["-q", "--output-repo-root", "."], &sub);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(dir.path().join(".trxx/all_content.md").is_file());
+    assert!(!sub.join("all_content.md").exists());
+}
+
+#[test]
+fn verify_honors_root_prefix() {
+    let dir = tree(&[("backend/src/main.rs", b"fn main() {}\n"), ("frontend/index.js", b"x;\n")]);
+    let mut bundle = Vec::new();
+    for name in ["backend", "frontend"] {
+        let output = trxx(&["-q", "--root-prefix", name, "--stdout", name], dir.path());
+        assert!(output.status.success(), "{}", stderr(&output));
+        bundle.extend(output.stdout);
+    }
+    write(dir.path(), "all.md", &bundle);
+
+    let output = trxx(&["--root-prefix", "backend", "verify", "all.md", "--dir", "backend"], dir.path());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+
+    write(dir.path(), "backend/src/main.rs", b"fn main() { changed(); }\n");
+    let output = trxx(&["--root-prefix", "backend", "verify", "all.md", "--dir", "backend"], dir.path());
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("不同  src/main.rs"));
+}
+
+#[test]
+fn config_file_is_loaded_and_output_flag_overrides_it() {
+    let dir = tree(&[
+        (".trxx.toml", b"output = \"from-config.md\"\nexclude = [\"*.log\"]\n"),
+        ("a.txt", b"a\n"),
+        ("debug.log", b"noise\n"),
+    ]);
+    let output = trxx(&["-q", "."], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    let packed = std::fs::read_to_string(dir.path().join("from-config.md")).unwrap();
+    assert!(packed.contains("###  trxx:a.txt") && !packed.contains("debug.log"));
+    std::fs::remove_file(dir.path().join("from-config.md")).unwrap();
+
+    let output = trxx(&["-q", "-o", "from-cli.md", "."], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(dir.path().join("from-cli.md").is_file());
+    assert!(!dir.path().join("from-config.md").exists());
+}
+
+#[test]
+fn verify_reports_modified_files() {
+    let dir = tree(&[("project/a.txt", b"a\n"), ("project/src/b.rs", b"fn b() {}\n")]);
+    let output = trxx(&["-q", "-o", "bundle.md", "project"], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = trxx(&["verify", "bundle.md", "--dir", "project"], dir.path());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+
+    write(dir.path(), "project/src/b.rs", b"fn b() { todo!() }\n");
+    let output = trxx(&["verify", "bundle.md", "--dir", "project"], dir.path());
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("不同  src/b.rs") && !stdout.contains("a.txt"), "{}", stdout);
+}
+
+#[test]
+fn gzip_bundle_round_trips() {
+    let dir = tree(&[("project/a.txt", b"hello\n"), ("project/src/x.rs", b"fn x() {}\n")]);
+    let output = trxx(&["-q", "--compress", "gzip", "-o", "bundle.md", "project"], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    let packed = std::fs::read(dir.path().join("bundle.md.gz")).unwrap();
+    assert_eq!(&packed[..2], &[0x1f, 0x8b]);
+    assert!(!dir.path().join("bundle.md").exists());
+
+    let output = trxx(&["revert", "bundle.md.gz", "--output-dir", "out"], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(read_tree(&dir.path().join("out")), read_tree(&dir.path().join("project")));
+}
+
+#[test]
+fn token_estimate_is_close_for_known_input() {
+    // 4000 个 ASCII 字符约 1000 个 token，800 个汉字约 800 个 token
+    let ascii = "word ".repeat(800);
+    let chinese = "汉字".repeat(400);
+    let dir = tree(&[("project/a.txt", ascii.as_bytes()), ("project/b.md", chinese.as_bytes())]);
+    let output = trxx(&["--tokens", "--token-limit", "1000", "--stdout", "project"], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    let err = stderr(&output);
+    let total = err
+        .lines()
+        .find_map(|line| line.strip_prefix("估算 token 数: ~"))
+        .unwrap_or_else(|| panic!("{}", err));
+    let total: f64 = match total.strip_suffix('k') {
+        Some(k) => k.parse::<f64>().unwrap() * 1000.0,
+        None => total.parse().unwrap(),
+    };
+    assert!((1800.0..1900.0).contains(&total), "{}", err);
+    assert!(err.contains("超过了上限"), "{}", err);
+}
+
+#[test]
+fn split_size_writes_parts_that_revert_to_original() {
+    let line = "x".repeat(99) + "\n";
+    let dir = tree(&[
+        ("project/a.txt", line.as_bytes()),
+        ("project/b.txt", line.as_bytes()),
+        ("project/src/c.rs", line.as_bytes()),
+    ]);
+    // 每个文件的段落约 130 字节，200 字节的上限只能放下一个
+    let output = trxx(&["-q", "--split-size", "200", "-o", "bundle.md", "project"], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    for part in ["bundle.001.md", "bundle.002.md", "bundle.003.md"] {
+        assert!(dir.path().join(part).is_file(), "缺少 {}", part);
+    }
+    assert!(!dir.path().join("bundle.004.md").exists());
+
+    let output = trxx(&["revert", "bundle.*.md", "--output-dir", "out"], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(read_tree(&dir.path().join("out")), read_tree(&dir.path().join("project")));
+}
+
+#[test]
+fn list_prints_the_files_that_would_be_packed() {
+    let dir = tree(&[
+        ("a.txt", b"a\n"),
+        ("src/x.rs", b"fn x() {}\n"),
+        ("debug.log", b"noise\n"),
+        ("node_modules/m/index.js", b"m;\n"),
+    ]);
+    let output = trxx(&["-q", "--exclude", "*.log", "list", "."], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let listed: Vec<&str> = stdout
+        .lines()
+        .filter(|line| !line.starts_with("共 "))
+        .map(|line| line.split_whitespace().next().unwrap())
+        .collect();
+    assert!(stdout.contains("src/x.rs        10 B  rust"), "{}", stdout);
+    assert!(stdout.contains("共 2 个文件，12 B"), "{}", stdout);
+
+    let output = trxx(&["-q", "--exclude", "*.log", "--stdout", "."], dir.path());
+    let packed = String::from_utf8_lossy(&output.stdout).into_owned();
+    let packed: Vec<&str> = packed.lines().filter_map(|line| line.strip_prefix(trxx::DEFAULT_HEADER_PREFIX)).collect();
+    assert_eq!(listed, packed);
+    assert_eq!(listed, ["a.txt", "src/x.rs"]);
+}
+
+#[test]
+fn dry_run_creates_no_file() {
+    let dir = tree(&[("project/a.txt", b"a\n"), ("project/b.txt", b"b\n")]);
+    for args in [&["--dry-run", "-o", "bundle.md", "project"][..], &["--dry-run", "project"]] {
+        let output = trxx(args, dir.path());
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(stderr(&output).contains("将包含 2 个文件"), "{}", stderr(&output));
+    }
+    let output = trxx(&["--dry-run", "--compress", "gzip", "--split-size", "1K", "-o", "bundle.md", "project"], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(read_tree(dir.path()).into_keys().collect::<Vec<_>>(), ["project/a.txt", "project/b.txt"]);
+}
+
+#[test]
+fn keep_going_skips_failed_file_and_reports_it() {
+    // 以 root 运行测试时权限位无法让文件不可读，这里用关闭编码检测后的无效 UTF-8 文件模拟读取失败
+    let dir = tree(&[
+        ("project/a.txt", b"a\n"),
+        ("project/b.txt", b"b\n"),
+        ("project/src/c.rs", b"fn c() {}\n"),
+        ("project/bad.txt", b"bad \xff\xfe\xc3 bytes\n"),
+    ]);
+    let output = trxx(&["--no-encoding-detect", "-o", "bundle.md", "project"], dir.path());
+    assert!(!output.status.success());
+    assert!(!dir.path().join("bundle.md").exists());
+
+    let output = trxx(&["--no-encoding-detect", "--keep-going", "-o", "bundle.md", "project"], dir.path());
+    assert_eq!(output.status.code(), Some(1));
+    let err = stderr(&output);
+    assert!(err.contains("共有 1 个文件处理失败，已跳过") && err.contains("  bad.txt: "), "{}", err);
+    let packed = std::fs::read_to_string(dir.path().join("bundle.md")).unwrap();
+    let headers: Vec<&str> = packed.lines().filter_map(|line| line.strip_prefix(trxx::DEFAULT_HEADER_PREFIX)).collect();
+    assert_eq!(headers, ["a.txt", "b.txt", "src/c.rs"]);
+}
+
+#[test]
+fn progress_does_not_alter_bundle() {
+    let files: Vec<(String, String)> = (0..50).map(|i| (format!("project/f{:02}.txt", i), format!("file {}\n", i))).collect();
+    let files: Vec<(&str, &[u8])> = files.iter().map(|(path, content)| (path.as_str(), content.as_bytes())).collect();
+    let dir = tree(&files);
+
+    let plain = trxx(&["-q", "--stdout", "project"], dir.path());
+    let with_progress = trxx(&["-q", "--progress", "--stdout", "project"], dir.path());
+    assert!(with_progress.status.success(), "{}", stderr(&with_progress));
+    assert_eq!(String::from_utf8_lossy(&with_progress.stdout), String::from_utf8_lossy(&plain.stdout));
+
+    let opts = trxx::PackOptions { progress: true, ..Default::default() };
+    let project = dir.path().join("project");
+    assert_eq!(trxx::pack(&project, &opts).unwrap(), trxx::pack(&project, &Default::default()).unwrap());
+}
+
+#[test]
+fn quiet_suppresses_ignore_messages() {
+    let dir = tree(&[
+        ("project/a.txt", b"a\n"),
+        ("project/node_modules/m/index.js", b"m;\n"),
+        ("project/README", b"readme\n"),
+    ]);
+    let verbose = trxx(&["-v", "--stdout", "project"], dir.path());
+    let err = stderr(&verbose);
+    assert!(err.contains("node_modules/m/index.js：自动忽略的目录或文件"), "{}", err);
+
+    let default = trxx(&["--stdout", "project"], dir.path());
+    let err = stderr(&default);
+    assert!(!err.contains("index.js") && err.contains("跳过了 1 个文件"), "{}", err);
+
+    let quiet = trxx(&["-q", "--stdout", "project"], dir.path());
+    assert!(quiet.status.success());
+    assert_eq!(stderr(&quiet), "");
+    assert_eq!(quiet.stdout, default.stdout);
+}
+
+// 等待条件成立，超时返回 false
+fn wait_for(mut done: impl FnMut() -> bool) -> bool {
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(20);
+    while std::time::Instant::now() < deadline {
+        if done() {
+            return true;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    false
+}
+
+#[test]
+fn watch_repacks_on_change() {
+    let dir = tree(&[("project/a.txt", b"before\n"), ("project/node_modules/m/index.js", b"m;\n")]);
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_trxx"))
+        .args(["-q", "-o", "bundle.md", "watch", "project"])
+        .current_dir(dir.path())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let bundle = dir.path().join("bundle.md");
+    let packed = || std::fs::read_to_string(&bundle).unwrap_or_default();
+
+    let first = wait_for(|| packed().contains("before"));
+    // 监视器在第一次打包之后才开始工作，稍等一下再修改文件
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    write(dir.path(), "project/a.txt", b"after\n");
+    let repacked = wait_for(|| packed().contains("after"));
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert!(first, "没有生成第一次的打包文件");
+    assert!(repacked, "修改文件后没有重新打包:\n{}", packed());
+    assert!(!packed().contains("node_modules"));
+}
+
+// 用指定格式打包 project 目录，再还原到 out 目录，返回打包文件的内容
+fn cli_round_trip(dir: &std::path::Path, format: &str, output: &str) -> Vec<u8> {
+    let packed = trxx(&["-q", "--format", format, "-o", output, "project"], dir);
+    assert!(packed.status.success(), "{}", stderr(&packed));
+    let reverted = trxx(&["revert", output, "--output-dir", "out"], dir);
+    assert!(reverted.status.success(), "{}", stderr(&reverted));
+    assert_eq!(read_tree(&dir.join("out")), read_tree(&dir.join("project")));
+    std::fs::read(dir.join(output)).unwrap()
+}
+
+#[test]
+fn json_format_round_trips() {
+    let dir = tree(&[
+        ("project/a.txt", b"hello\n"),
+        ("project/src/main.rs", b"fn main() {}\n"),
+        ("project/README.md", b"# demo\n```sh\nrun\n```\n"),
+        ("project/logo.png", &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0xff]),
+    ]);
+    let packed = cli_round_trip(dir.path(), "json", "bundle.json");
+    let json: serde_json::Value = serde_json::from_slice(&packed).unwrap();
+    let entry = json.as_array().unwrap().iter().find(|e| e["path"] == "logo.png").unwrap();
+    assert_eq!(entry["binary"], true);
+}
+
+#[test]
+fn tar_format_round_trips_binary_and_text() {
+    let mut blob: Vec<u8> = (0..=255u8).collect();
+    blob.extend_from_slice(b"\0\r\n\xff");
+    let dir = tree(&[
+        ("project/a.txt", b"line\r\nno newline"),
+        ("project/data/blob.bin", &blob),
+        ("project/legacy.txt", b"caf\xe9\n"),
+    ]);
+    let packed = cli_round_trip(dir.path(), "tar", "bundle.tar");
+    assert_eq!(&packed[257..262], b"ustar");
+}
+
+#[test]
+fn zip_format_round_trips_nested_dirs() {
+    let dir = tree(&[
+        ("project/a.txt", b"a\n"),
+        ("project/src/lib.rs", b"pub fn f() {}\n"),
+        ("project/src/deep/er/mod.rs", b"mod x;\n"),
+        ("project/assets/icon.png", &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0]),
+    ]);
+    let packed = cli_round_trip(dir.path(), "zip", "bundle.zip");
+    assert_eq!(&packed[..4], b"PK\x03\x04");
+}
+
+#[test]
+fn stats_counts_files_per_language() {
+    let dir = tree(&[
+        ("src/main.rs", b"fn main() {}\n"),
+        ("src/lib.rs", b"pub fn f() {}\n"),
+        ("app.py", b"print(1)\n"),
+        ("logo.png", &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0]),
+        ("big.txt", &[b'x'; 2 * 1024 * 1024]),
+    ]);
+    let output = trxx(&["-q", "stats", "--json", "."], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stats: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(stats["files"], 4);
+    assert_eq!(stats["binary_files"], 1);
+    assert_eq!(stats["skipped_by_size"], 1);
+    assert_eq!(stats["languages"]["rust"]["files"], 2);
+    assert_eq!(stats["languages"]["rust"]["bytes"], 27);
+    assert_eq!(stats["languages"]["python"]["files"], 1);
+
+    let output = trxx(&["-q", "stats", "."], dir.path());
+    let table = String::from_utf8_lossy(&output.stdout);
+    assert!(table.contains("共 4 个文件") && table.contains("其中二进制文件 1 个，因大小限制跳过 1 个"), "{}", table);
+}
+
+#[test]
+fn skip_reasons_are_tallied() {
+    let dir = tree(&[
+        ("project/a.txt", b"a\n"),
+        ("project/big.txt", &[b'x'; 2 * 1024 * 1024]),
+        ("project/target/debug/out.txt", b"x\n"),
+    ]);
+    let output = trxx(&["-o", "bundle.md", "project"], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    let err = stderr(&output);
+    assert!(err.contains("跳过了 2 个文件（自动忽略的目录或文件 1，超出大小限制 1）"), "{}", err);
+
+    let output = trxx(&["--list-skipped", "-o", "bundle.md", "project"], dir.path());
+    let err = stderr(&output);
+    assert!(err.contains("big.txt") && err.contains("out.txt"), "{}", err);
+}
@@ -0,0 +1,37 @@
+// 需要启用 clipboard 功能：cargo test --features clipboard
+#![cfg(feature = "clipboard")]
+
+mod common;
+
+use common::*;
+use trxx::{Clipboard, PackOptions};
+
+// 记录写入内容的剪贴板，不访问系统剪贴板
+#[derive(Default)]
+struct MockClipboard {
+    texts: Vec<String>,
+}
+
+impl Clipboard for MockClipboard {
+    fn set_text(&mut self, text: &str) -> anyhow::Result<()> {
+        self.texts.push(text.to_string());
+        Ok(())
+    }
+}
+
+#[test]
+fn bundle_is_written_to_clipboard() {
+    let dir = tree(&[("a.txt", b"hello\n")]);
+    let content = trxx::pack(dir.path(), &PackOptions::default()).unwrap();
+    let mut clipboard = MockClipboard::default();
+    trxx::copy_to_clipboard(&mut clipboard, content.as_bytes()).unwrap();
+    assert_eq!(clipboard.texts, [content]);
+}
+
+#[test]
+fn binary_bundle_is_not_written_to_clipboard() {
+    let mut clipboard = MockClipboard::default();
+    let err = trxx::copy_to_clipboard(&mut clipboard, b"PK\x03\x04\xff\xfe").unwrap_err();
+    assert!(err.to_string().contains("不是文本"), "{}", err);
+    assert!(clipboard.texts.is_empty());
+}
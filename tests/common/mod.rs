@@ -0,0 +1,76 @@
+// 集成测试共用的辅助函数：在临时目录中创建文件树、打包、还原并比较
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+use trxx::{pack, revert, PackOptions};
+
+// 临时目录的名字不能以点开头，否则整个目录会被当成隐藏目录忽略
+pub fn temp_dir() -> TempDir {
+    tempfile::Builder::new().prefix("trxx-test-").tempdir().unwrap()
+}
+
+// 按 (相对路径, 内容) 创建文件，自动创建父目录
+pub fn tree(files: &[(&str, &[u8])]) -> TempDir {
+    let dir = temp_dir();
+    for (path, content) in files {
+        write(dir.path(), path, content);
+    }
+    dir
+}
+
+pub fn write(root: &Path, path: &str, content: &[u8]) -> PathBuf {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, content).unwrap();
+    path
+}
+
+// 目录下所有文件的相对路径和内容
+pub fn read_tree(root: &Path) -> BTreeMap<String, Vec<u8>> {
+    let mut files = BTreeMap::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                stack.push(path);
+            } else {
+                let rel = path.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/");
+                files.insert(rel, fs::read(&path).unwrap());
+            }
+        }
+    }
+    files
+}
+
+// 打包后还原到新的临时目录
+pub fn round_trip(dir: &Path, opts: &PackOptions) -> (String, TempDir) {
+    let content = pack(dir, opts).unwrap();
+    let out = temp_dir();
+    revert(&content, out.path()).unwrap();
+    (content, out)
+}
+
+pub fn assert_round_trip(dir: &Path, opts: &PackOptions) -> String {
+    let (content, out) = round_trip(dir, opts);
+    assert_eq!(read_tree(out.path()), read_tree(dir), "还原结果与原目录不一致:\n{}", content);
+    content
+}
+
+// 运行编译好的 trxx 命令
+pub fn trxx(args: &[&str], cwd: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_trxx"))
+        .args(args)
+        .current_dir(cwd)
+        .output()
+        .unwrap()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use common::*;
+use trxx::PackOptions;
+
+// 文件头后面的代码块开头一行，例如 "```rust" 或 "```binary"
+fn fence_line<'a>(content: &'a str, path: &str) -> &'a str {
+    let header = format!("{}{}\n", trxx::DEFAULT_HEADER_PREFIX, path);
+    let start = content.find(&header).unwrap_or_else(|| panic!("缺少文件 {}:\n{}", path, content));
+    content[start..].lines().find(|line| line.starts_with("```")).unwrap()
+}
+
+#[test]
+fn binary_content_is_base64_regardless_of_extension() {
+    let mut blob = vec![0x13, 0x37, 0, 0xff, 0xfe, 0];
+    blob.extend((0..200u8).map(|b| b.wrapping_mul(31)));
+    let dir = tree(&[
+        ("doc.pdf", b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n1 0 obj\n<< >>\nendobj\n"),
+        ("data.csv", b"id,name\n1,alice\n"),
+        ("sub/blob.bin", &blob),
+    ]);
+    let content = assert_round_trip(dir.path(), &PackOptions::default());
+    assert!(fence_line(&content, "doc.pdf").ends_with("binary"));
+    assert!(fence_line(&content, "sub/blob.bin").ends_with("binary"));
+    assert!(!fence_line(&content, "data.csv").ends_with("binary"));
+    assert!(content.contains("1,alice"));
+}
+
+#[test]
+fn gbk_file_round_trips() {
+    let text = "// 这是一个使用 GBK 编码保存的源文件，用来测试编码检测\nfn main() {\n    println!(\"你好，世界！中文字符在还原后应该保持不变。\");\n}\n";
+    let (gbk, _, unmappable) = encoding_rs::GBK.encode(text);
+    assert!(!unmappable);
+    let dir = tree(&[("legacy.rs", &gbk)]);
+    let content = assert_round_trip(dir.path(), &PackOptions { encoding_detect: true, ..Default::default() });
+    assert!(content.contains("你好，世界"), "{}", content);
+    assert!(content.contains("<!-- encoding=GBK -->"), "{}", content);
+}
+
+#[test]
+fn well_known_filenames_get_a_language() {
+    let dir = tree(&[
+        ("Dockerfile", b"FROM rust:1\nRUN cargo build\n"),
+        ("Makefile", b"all:\n\tcargo build\n"),
+        ("docker/Dockerfile", b"FROM alpine\n"),
+    ]);
+    let opts = PackOptions { extensionless: trxx::Extensionless::Deny, ..Default::default() };
+    let content = assert_round_trip(dir.path(), &opts);
+    assert_eq!(fence_line(&content, "Dockerfile"), "```dockerfile");
+    assert_eq!(fence_line(&content, "docker/Dockerfile"), "```dockerfile");
+    assert_eq!(fence_line(&content, "Makefile"), "```makefile");
+}
+
+#[test]
+fn shebang_scripts_get_a_language() {
+    let dir = tree(&[
+        ("bin/deploy", b"#!/bin/bash\nset -e\necho deploy\n"),
+        ("bin/tool", b"#!/usr/bin/env python3\nprint('tool')\n"),
+    ]);
+    let content = assert_round_trip(dir.path(), &PackOptions::default());
+    assert_eq!(fence_line(&content, "bin/deploy"), "```bash");
+    assert_eq!(fence_line(&content, "bin/tool"), "```python");
+    assert!(content.contains("#!/usr/bin/env python3\nprint('tool')\n"));
+}
+
+#[test]
+fn lang_flag_adds_and_overrides_mappings() {
+    let dir = tree(&[("build.zig", b"const std = @import(\"std\");\n"), ("lib.rs", b"pub fn f() {}\n")]);
+    // 关闭内容嗅探，未知扩展名的文本文件只有通过 --lang 映射后才会被打包
+    let output = trxx(&["-q", "--no-sniff", "--stdout", "."], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("###  trxx:build.zig\n"));
+
+    let output = trxx(&["-q", "--no-sniff", "--lang", "zig=zig", "--lang", ".RS=text", "--stdout", "."], dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    let content = String::from_utf8(output.stdout).unwrap();
+    assert!(content.contains("###  trxx:build.zig\n"), "{}", content);
+    assert_eq!(fence_line(&content, "build.zig"), "```zig");
+    assert_eq!(fence_line(&content, "lib.rs"), "```text");
+
+    let output = trxx(&["--lang", "zig", "--stdout", "."], dir.path());
+    assert!(!output.status.success());
+}
+
+#[test]
+fn external_lang_map_replaces_or_merges() {
+    let maps = tree(&[("langs.json", b"{\".tpl\": \"handlebars\", \"rs\": \"rust\"}\n"), ("broken.json", b"{\"tpl\": \n")]);
+    let map = trxx::load_lang_map(&maps.path().join("langs.json")).unwrap();
+    assert_eq!(map.get("tpl").map(String::as_str), Some("handlebars"));
+    let err = trxx::load_lang_map(&maps.path().join("broken.json")).unwrap_err();
+    assert!(format!("{:#}", err).contains("broken.json"), "{:#}", err);
+
+    let dir = tree(&[("page.tpl", b"<p>{{name}}</p>\n"), ("main.py", b"print(1)\n")]);
+    let content = assert_round_trip(dir.path(), &PackOptions { lang_map: Some(map.clone()), ..Default::default() });
+    assert_eq!(fence_line(&content, "page.tpl"), "```handlebars");
+    assert_eq!(fence_line(&content, "main.py"), "```");
+
+    let content = trxx::pack(dir.path(), &PackOptions { extensions: map, ..Default::default() }).unwrap();
+    assert_eq!(fence_line(&content, "page.tpl"), "```handlebars");
+    assert_eq!(fence_line(&content, "main.py"), "```python");
+}
+
+#[test]
+fn utf16_le_script_round_trips_with_bom() {
+    let script = "Write-Host \"你好\"\r\n$x = 1\r\n";
+    let mut bytes = vec![0xff, 0xfe];
+    bytes.extend(script.encode_utf16().flat_map(u16::to_le_bytes));
+    let dir = tree(&[("setup.ps1", &bytes)]);
+    let content = assert_round_trip(dir.path(), &PackOptions { encoding_detect: true, ..Default::default() });
+    assert!(content.contains("<!-- encoding=UTF-16LE -->"), "{}", content);
+    assert!(content.contains("Write-Host \"你好\""), "{}", content);
+}
+
+#[test]
+fn jsonc_and_json5_are_recognized() {
+    let dir = tree(&[
+        ("tsconfig.jsonc", b"{\n  // comment\n  \"strict\": true,\n}\n"),
+        ("config.json5", b"{ key: 'value' }\n"),
+    ]);
+    // 关闭内容嗅探，两个文件只能靠扩展名映射被打包，还原结果与原目录一致说明都已打包
+    let content = assert_round_trip(dir.path(), &PackOptions { sniff: false, ..Default::default() });
+    assert_eq!(fence_line(&content, "tsconfig.jsonc"), "```jsonc");
+    assert_eq!(fence_line(&content, "config.json5"), "```json5");
+}
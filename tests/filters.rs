@@ -0,0 +1,213 @@
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+
+use common::*;
+use trxx::PackOptions;
+
+// 以指定作者提交目录中的全部改动
+fn git_commit(dir: &Path, author: &str) {
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", &format!("user.name={}", author), "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} 失败", args);
+    };
+    if !dir.join(".git").exists() {
+        git(&["init", "-q"]);
+    }
+    git(&["add", "-A"]);
+    git(&["commit", "-q", "-m", author]);
+}
+
+fn packed_paths(dir: &Path, opts: &PackOptions) -> Vec<String> {
+    trxx::pack(dir, opts).unwrap()
+        .lines()
+        .filter_map(|line| line.strip_prefix(trxx::DEFAULT_HEADER_PREFIX))
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn git_author_keeps_non_ascii_paths() {
+    let dir = tree(&[("说明.txt", b"a\n"), ("docs/b.txt", b"b\n")]);
+    git_commit(dir.path(), "alice");
+    write(dir.path(), "c.txt", b"c\n");
+    git_commit(dir.path(), "bob");
+
+    let opts = PackOptions { git_author: Some("alice".to_string()), ..Default::default() };
+    assert_eq!(packed_paths(dir.path(), &opts), ["docs/b.txt", "说明.txt"]);
+}
+
+#[test]
+fn ignored_dirs_only_match_inside_the_packed_tree() {
+    // 在 Windows 上遍历得到的路径使用 \ 分隔，同样按路径组成部分匹配
+    let root = tree(&[
+        ("target/proj/src/main.rs", b"fn main() {}\n"),
+        ("target/proj/target/debug/out.txt", b"x\n"),
+        ("target/proj/web/node_modules/lib/index.js", b"x\n"),
+        ("target/proj/my-target-dir/a.txt", b"a\n"),
+    ]);
+    std::fs::create_dir_all(root.path().join("target/proj/empty")).unwrap();
+    std::fs::create_dir_all(root.path().join("target/proj/target/empty")).unwrap();
+    let proj = root.path().join("target/proj");
+    let opts = PackOptions { keep_empty_dirs: true, ..Default::default() };
+    assert_eq!(packed_paths(&proj, &opts), ["my-target-dir/a.txt", "src/main.rs"]);
+    let content = trxx::pack(&proj, &opts).unwrap();
+    assert!(content.contains("###  trxx-dir:empty/"));
+    assert!(!content.contains("target/empty"));
+}
+
+#[test]
+fn ignored_dirs_match_backslash_separated_paths() {
+    // Unix 上文件名可以包含 \，这类路径同样按 \ 拆分后匹配需要忽略的目录
+    let dir = tree(&[
+        ("target\\debug\\out.txt", b"x\n"),
+        ("web\\node_modules\\index.js", b"x\n"),
+        ("my-target-dir\\a.txt", b"a\n"),
+    ]);
+    let paths = packed_paths(dir.path(), &PackOptions::default());
+    assert_eq!(paths.len(), 1, "{:?}", paths);
+    assert!(paths[0].starts_with("my-target-dir") && paths[0].ends_with("a.txt"), "{:?}", paths);
+}
+
+#[test]
+fn trxxignore_excludes_with_negation() {
+    let dir = tree(&[
+        (".trxxignore", b"*.snap\n!important.snap\n"),
+        ("a.snap", b"a\n"),
+        ("tests/b.snap", b"b\n"),
+        ("tests/important.snap", b"keep\n"),
+        ("main.rs", b"fn main() {}\n"),
+    ]);
+    assert_eq!(packed_paths(dir.path(), &PackOptions::default()), ["main.rs", "tests/important.snap"]);
+}
+
+#[test]
+fn exclude_matches_files_and_directories() {
+    let dir = tree(&[
+        ("app.js", b"a;\n"),
+        ("vendor/jquery.min.js", b"j;\n"),
+        ("dist/app.min.js", b"d;\n"),
+        ("docs/guide.md", b"# guide\n"),
+        ("docs/api/index.md", b"# api\n"),
+    ]);
+    let opts = PackOptions { exclude: vec!["**/*.min.js".to_string()], ..Default::default() };
+    assert_eq!(packed_paths(dir.path(), &opts), ["app.js", "docs/api/index.md", "docs/guide.md"]);
+
+    let opts = PackOptions { exclude: vec!["docs".to_string()], ..Default::default() };
+    assert_eq!(packed_paths(dir.path(), &opts), ["app.js", "dist/app.min.js", "vendor/jquery.min.js"]);
+}
+
+#[test]
+fn include_only_picks_matching_files() {
+    let dir = tree(&[("src/main.rs", b"fn main() {}\n"), ("src/app.js", b"a;\n"), ("README.md", b"# r\n"), ("lib.rs", b"\n")]);
+    let opts = PackOptions { include: vec!["**/*.rs".to_string()], ..Default::default() };
+    assert_eq!(packed_paths(dir.path(), &opts), ["lib.rs", "src/main.rs"]);
+}
+
+#[test]
+fn max_size_is_configurable() {
+    let big = "0123456789abcdef\n".repeat(2 * 1024 * 1024 / 17 + 1);
+    let dir = tree(&[("big.txt", big.as_bytes()), ("small.txt", b"s\n")]);
+    assert_eq!(packed_paths(dir.path(), &PackOptions::default()), ["small.txt"]);
+
+    let opts = PackOptions { max_size: Some(trxx::parse_size("4M").unwrap()), ..Default::default() };
+    assert_eq!(packed_paths(dir.path(), &opts), ["big.txt", "small.txt"]);
+}
+
+#[test]
+fn max_depth_limits_recursion() {
+    let dir = tree(&[("top.txt", b"t\n"), ("a/b.txt", b"b\n"), ("a/c/d.txt", b"d\n")]);
+    let opts = PackOptions { max_depth: Some(1), ..Default::default() };
+    assert_eq!(packed_paths(dir.path(), &opts), ["a/b.txt", "top.txt"]);
+}
+
+#[test]
+fn dotfiles_are_packed_only_with_hidden() {
+    let dir = tree(&[
+        (".env", b"KEY=value\n"),
+        (".github/workflows/ci.yml", b"on: push\n"),
+        (".git/config", b"[core]\n"),
+        ("node_modules/.bin/tool", b"tool\n"),
+        ("a.txt", b"a\n"),
+    ]);
+    assert_eq!(packed_paths(dir.path(), &PackOptions::default()), ["a.txt"]);
+
+    let hidden = PackOptions { hidden: true, ..Default::default() };
+    assert_eq!(packed_paths(dir.path(), &hidden), [".env", ".github/workflows/ci.yml", "a.txt"]);
+
+    let output = trxx(&["-q", "--hidden", "--stdout", "."], dir.path());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("###  trxx:.env\n"), "{}", stderr(&output));
+}
+
+#[test]
+fn files_from_packs_listed_paths_in_order() {
+    let dir = tree(&[("a.txt", b"a\n"), ("b.txt", b"b\n"), ("src/c.rs", b"fn c() {}\n"), ("src/d.rs", b"fn d() {}\n")]);
+    let lists = temp_dir();
+    let list = write(lists.path(), "files.txt", b"src/c.rs\n\na.txt\n  src/d.rs  \n");
+    let opts = PackOptions { files_from: Some(list), ..Default::default() };
+    assert_eq!(packed_paths(dir.path(), &opts), ["src/c.rs", "a.txt", "src/d.rs"]);
+
+    let list = write(lists.path(), "missing.txt", b"a.txt\nmissing.txt\n");
+    let opts = PackOptions { files_from: Some(list), ..Default::default() };
+    assert!(trxx::pack(dir.path(), &opts).is_err());
+    let opts = PackOptions { keep_going: true, ..opts };
+    assert_eq!(packed_paths(dir.path(), &opts), ["a.txt"]);
+}
+
+#[test]
+fn min_size_skips_small_files_except_images() {
+    let png = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0, 0, 0x0d];
+    let dir = tree(&[("small.txt", &[b'x'; 10]), ("large.txt", &[b'y'; 200]), ("icon.png", &png)]);
+    let opts = PackOptions { min_size: 100, ..Default::default() };
+    assert_eq!(packed_paths(dir.path(), &opts), ["icon.png", "large.txt"]);
+    assert_eq!(packed_paths(dir.path(), &PackOptions::default()), ["icon.png", "large.txt", "small.txt"]);
+}
+
+#[test]
+fn selection_filter_narrows_file_list() {
+    let root = Path::new("/repo");
+    let files: Vec<_> = ["README.md", "src/main.rs", "src/util/a.rs", "src/util/b.rs", "src/utility.rs"]
+        .iter()
+        .map(|rel| root.join(rel))
+        .collect();
+    let unselected = ["src/util", "README.md"].iter().map(std::path::PathBuf::from).collect();
+    let kept = trxx::apply_selection(files.clone(), root, &unselected);
+    assert_eq!(kept, [root.join("src/main.rs"), root.join("src/utility.rs")]);
+    assert_eq!(trxx::apply_selection(files.clone(), root, &Default::default()), files);
+}
+
+#[test]
+fn binary_and_text_size_limits_are_independent() {
+    let mut blob = vec![0u8; 3000];
+    blob[..4].copy_from_slice(b"\x7fELF");
+    let dir = tree(&[("app.bin", &blob), ("notes.txt", &[b'x'; 3000]), ("small.txt", b"s\n")]);
+    let limits = |text: u64, binary: Option<u64>| PackOptions { max_size: Some(text), binary_max_size: binary, ..Default::default() };
+
+    assert_eq!(packed_paths(dir.path(), &limits(1000, Some(5000))), ["app.bin", "small.txt"]);
+    assert_eq!(packed_paths(dir.path(), &limits(5000, Some(1000))), ["notes.txt", "small.txt"]);
+    assert_eq!(packed_paths(dir.path(), &limits(1000, None)), ["small.txt"]);
+    assert_eq!(packed_paths(dir.path(), &limits(5000, None)), ["app.bin", "notes.txt", "small.txt"]);
+}
+
+#[test]
+fn no_binary_excludes_png_and_keeps_text() {
+    let png = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0, 0, 0x0d];
+    let dir = tree(&[("logo.png", &png), ("notes.txt", b"notes\n")]);
+    assert_eq!(packed_paths(dir.path(), &PackOptions { no_binary: true, ..Default::default() }), ["notes.txt"]);
+    assert_eq!(packed_paths(dir.path(), &PackOptions { binary_only: true, ..Default::default() }), ["logo.png"]);
+}
+
+#[test]
+fn lang_only_keeps_matching_languages() {
+    let dir = tree(&[("src/main.rs", b"fn main() {}\n"), ("tool.py", b"print(1)\n"), ("README.md", b"# demo\n")]);
+    let opts = PackOptions { lang_only: vec!["rust".to_string()], ..Default::default() };
+    assert_eq!(packed_paths(dir.path(), &opts), ["src/main.rs"]);
+}
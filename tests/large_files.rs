@@ -0,0 +1,60 @@
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use common::*;
+use trxx::PackOptions;
+
+// 记录当前和峰值的堆内存占用
+struct PeakAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(current, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+#[test]
+fn large_binary_round_trips_with_bounded_memory() {
+    const SIZE: usize = 10 * 1024 * 1024;
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let blob: Vec<u8> = (0..SIZE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 24) as u8
+        })
+        .collect();
+    let dir = tree(&[("blob.bin", &blob)]);
+    drop(blob);
+
+    let opts = PackOptions { max_size: Some(0), binary_max_size: Some(0), ..Default::default() };
+    let before = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    let content = trxx::pack(dir.path(), &opts).unwrap();
+    let peak = PEAK.load(Ordering::SeqCst) - before;
+    // 只允许分段结果和拼接后的结果各占一份，不应再把整个文件读入内存
+    assert!(peak < content.len() * 2 + (1 << 20), "峰值内存 {} 字节，输出 {} 字节", peak, content.len());
+
+    let out = temp_dir();
+    trxx::revert(&content, out.path()).unwrap();
+    assert_eq!(read_tree(out.path()), read_tree(dir.path()));
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use std::path::Path;
+
+use common::*;
+use trxx::{PackOptions, RevertOptions};
+
+// 一个只包含单个文本文件的打包内容
+fn bundle(path: &str, body: &str) -> String {
+    format!("{}{}\n\n```\n\n{}\n\n```\n\n", trxx::DEFAULT_HEADER_PREFIX, path, body)
+}
+
+// 把打包内容写到临时文件后按 opts 还原
+fn revert_with(content: &str, opts: &RevertOptions) -> anyhow::Result<()> {
+    let dir = temp_dir();
+    let input = write(dir.path(), "bundle.md", content.as_bytes());
+    trxx::revert_files(&[input.to_string_lossy().into_owned()], opts)
+}
+
+fn into(dir: &Path) -> RevertOptions {
+    RevertOptions { output_dir: Some(dir.to_path_buf()), ..Default::default() }
+}
+
+#[test]
+fn output_dir_restores_under_target_and_rejects_traversal() {
+    let src = tree(&[("a.txt", b"a\n"), ("sub/b.txt", b"b\n")]);
+    let content = trxx::pack(src.path(), &PackOptions::default()).unwrap();
+    let out = temp_dir();
+    let target = out.path().join("restored/nested");
+    revert_with(&content, &into(&target)).unwrap();
+    assert_eq!(read_tree(&target), read_tree(src.path()));
+
+    let err = revert_with(&bundle("sub/../../escape.txt", "x"), &into(&target)).unwrap_err();
+    assert!(err.to_string().contains("包含 .."), "{}", err);
+    assert!(!out.path().join("restored/escape.txt").exists());
+}
+
+#[test]
+fn traversal_and_absolute_paths_are_refused() {
+    let out = temp_dir();
+    let outside = temp_dir();
+    let absolute = outside.path().join("abs.txt");
+    for path in ["../x", "a/../../x", "..\\x"] {
+        let err = revert_with(&bundle(path, "evil"), &into(out.path())).unwrap_err();
+        assert!(err.to_string().contains("包含 .."), "{}: {}", path, err);
+    }
+    let err = revert_with(&bundle(&absolute.to_string_lossy(), "evil"), &into(out.path())).unwrap_err();
+    assert!(err.to_string().contains("绝对路径"), "{}", err);
+    assert!(!absolute.exists());
+    assert!(read_tree(out.path()).is_empty());
+    assert!(!out.path().parent().unwrap().join("x").exists());
+
+    // 明确允许时绝对路径写回原来的位置
+    let opts = RevertOptions { allow_absolute: true, ..into(out.path()) };
+    revert_with(&bundle(&absolute.to_string_lossy(), "trusted"), &opts).unwrap();
+    assert_eq!(std::fs::read_to_string(&absolute).unwrap(), "trusted");
+}
+
+#[test]
+fn flipped_byte_fails_checksum() {
+    let src = tree(&[("a.txt", b"hello world\n"), ("b.txt", b"other\n")]);
+    let content = trxx::pack(src.path(), &PackOptions { checksum: true, ..Default::default() }).unwrap();
+    assert!(content.contains("<!-- sha256="));
+    let out = temp_dir();
+    revert_with(&content, &into(&out.path().join("ok"))).unwrap();
+
+    let corrupted = content.replacen("hello world", "hellp world", 1);
+    let err = revert_with(&corrupted, &into(&out.path().join("bad"))).unwrap_err();
+    assert!(err.to_string().contains("a.txt 的校验和不匹配"), "{}", err);
+}
+
+#[test]
+fn only_restores_matching_files() {
+    let src = tree(&[
+        ("README.md", b"# demo\n"),
+        ("src/main.rs", b"fn main() {}\n"),
+        ("src/util/mod.rs", b"pub fn f() {}\n"),
+        ("src/util/data.json", b"{}\n"),
+        ("docs/guide.md", b"guide\n"),
+    ]);
+    let content = trxx::pack(src.path(), &PackOptions::default()).unwrap();
+    let restored = |only: &[&str]| {
+        let out = temp_dir();
+        let opts = RevertOptions { only: only.iter().map(|p| p.to_string()).collect(), ..into(out.path()) };
+        revert_with(&content, &opts).unwrap();
+        read_tree(out.path()).into_keys().collect::<Vec<_>>()
+    };
+    assert_eq!(restored(&["src/main.rs"]), ["src/main.rs"]);
+    assert_eq!(restored(&["src/**/*.rs"]), ["src/main.rs", "src/util/mod.rs"]);
+    assert_eq!(restored(&["src/util", "README.md"]), ["README.md", "src/util/data.json", "src/util/mod.rs"]);
+}
+
+#[test]
+fn slash_separated_headers_revert_into_nested_dirs() {
+    let out = temp_dir();
+    let content = bundle("src/util/mod.rs", "pub fn f() {}") + &bundle("docs\\guide\\intro.md", "intro");
+    revert_with(&content, &into(out.path())).unwrap();
+    assert!(out.path().join("src").join("util").join("mod.rs").is_file());
+    assert!(out.path().join("docs").join("guide").join("intro.md").is_file());
+    assert_eq!(read_tree(out.path()).into_keys().collect::<Vec<_>>(), ["docs/guide/intro.md", "src/util/mod.rs"]);
+}
+
+#[test]
+fn truncated_bundle_restores_complete_files_and_the_partial_one() {
+    let src = tree(&[("a.txt", b"first\n"), ("b.txt", b"second\n"), ("c.txt", b"third line one\nthird line two\n")]);
+    let content = trxx::pack(src.path(), &PackOptions::default()).unwrap();
+    let cut = content.find("third line two").unwrap();
+    let out = temp_dir();
+    let bundle = write(out.path(), "bundle.md", &content.as_bytes()[..cut]);
+
+    let output = trxx(&["revert", "bundle.md", "--output-dir", "restored"], out.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("文件 c.txt 的代码块没有结束"), "{}", stderr(&output));
+    let restored = read_tree(&out.path().join("restored"));
+    assert_eq!(restored["a.txt"], b"first\n");
+    assert_eq!(restored["b.txt"], b"second\n");
+    assert_eq!(restored["c.txt"], b"third line one\n");
+
+    revert_with(&std::fs::read_to_string(bundle).unwrap(), &into(&out.path().join("lib"))).unwrap();
+    assert_eq!(read_tree(&out.path().join("lib")), restored);
+}
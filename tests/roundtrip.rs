@@ -0,0 +1,274 @@
+mod common;
+
+use common::*;
+use trxx::{OutputFormat, PackOptions};
+
+#[test]
+fn pack_and_revert_through_library() {
+    let dir = tree(&[
+        ("README.md", b"# demo\n"),
+        ("src/main.rs", b"fn main() {\n    println!(\"hi\");\n}\n"),
+        ("src/util/mod.rs", b"pub fn f() {}\n"),
+        ("logo.png", &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0, 0, 0x0d]),
+    ]);
+    let content = assert_round_trip(dir.path(), &PackOptions::default());
+    assert!(content.contains("###  trxx:src/util/mod.rs"));
+}
+
+#[test]
+fn pack_returns_empty_string_without_files() {
+    let dir = temp_dir();
+    assert_eq!(trxx::pack(dir.path(), &PackOptions::default()).unwrap(), "");
+}
+
+#[test]
+fn marker_lines_inside_file_body_are_content() {
+    let body = b"line1\n###  trxx-dir:evil/\n###  trxx-manifest:\n###  trxx:evil.txt\nline5\n";
+    let dir = tree(&[("notes.txt", body), ("docs/a.md", b"###  trxx-dir:evil/\n###  trxx-manifest:\n")]);
+    std::fs::create_dir(dir.path().join("empty")).unwrap();
+    let opts = PackOptions { manifest: true, keep_empty_dirs: true, ..Default::default() };
+    let (_, out) = round_trip(dir.path(), &opts);
+    assert_eq!(read_tree(out.path()), read_tree(dir.path()));
+    assert!(out.path().join("empty").is_dir());
+    assert!(!out.path().join("evil").exists());
+}
+
+#[test]
+fn manifest_lists_files_and_is_ignored_on_revert() {
+    let dir = tree(&[("a.txt", b"hello\n"), ("src/lib.rs", b"pub fn f() {}\n")]);
+    for prefix in [trxx::DEFAULT_HEADER_PREFIX, "@@ file: "] {
+        let opts = PackOptions { manifest: true, header_prefix: prefix.to_string(), ..Default::default() };
+        let content = trxx::pack(dir.path(), &opts).unwrap();
+        let manifest = content.split(&format!("{}a.txt", prefix)).next().unwrap();
+        assert!(manifest.contains("a.txt") && manifest.contains("src/lib.rs"), "{}", content);
+        assert!(manifest.contains("共 2 个文件"));
+
+        let out = temp_dir();
+        trxx::revert_files(&[write(out.path(), "bundle.md", content.as_bytes()).to_string_lossy().into_owned()], &trxx::RevertOptions {
+            output_dir: Some(out.path().join("restored")),
+            header_prefix: Some(prefix.to_string()),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(read_tree(&out.path().join("restored")), read_tree(dir.path()));
+    }
+}
+
+#[test]
+fn custom_header_prefix_round_trip() {
+    let prefix = "==> file: ";
+    let body = b"###  trxx:a.txt\n==> file: evil.txt\n==> file-dir:evil/\n###  trxx-dir:evil/\n";
+    let dir = tree(&[("a.txt", body), ("b/c.rs", b"fn c() {}\n")]);
+    std::fs::create_dir(dir.path().join("empty")).unwrap();
+    let opts = PackOptions { header_prefix: prefix.to_string(), keep_empty_dirs: true, ..Default::default() };
+    let content = trxx::pack(dir.path(), &opts).unwrap();
+    assert!(content.contains("==> file: b/c.rs\n"));
+    assert!(content.contains("==> file-dir:empty/\n"));
+
+    let out = temp_dir();
+    let bundle = write(out.path(), "bundle.md", content.as_bytes());
+    trxx::revert_files(&[bundle.to_string_lossy().into_owned()], &trxx::RevertOptions {
+        output_dir: Some(out.path().join("restored")),
+        header_prefix: Some(prefix.to_string()),
+        ..Default::default()
+    }).unwrap();
+    let restored = out.path().join("restored");
+    assert_eq!(read_tree(&restored), read_tree(dir.path()));
+    assert!(restored.join("empty").is_dir());
+    assert!(!restored.join("evil").exists());
+}
+
+#[test]
+fn dedup_writes_one_section_and_a_reference() {
+    let dir = tree(&[("a/config.json", b"{\"k\": 1}\n"), ("b/config.json", b"{\"k\": 1}\n"), ("c.json", b"{}\n")]);
+    let opts = PackOptions { dedup: true, ..Default::default() };
+    let content = assert_round_trip(dir.path(), &opts);
+    assert_eq!(content.matches("{\"k\": 1}").count(), 1);
+    assert!(content.contains("###  trxx:b/config.json\n<!-- same-as=a/config.json -->\n"));
+}
+
+#[test]
+fn dedup_reference_to_placeholder_is_not_restored() {
+    let png = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0, 0, 0x0d, b'I', b'H', b'D', b'R'];
+    let dir = tree(&[("a.png", &png), ("b.png", &png), ("c.txt", b"c\n")]);
+    let opts = PackOptions { dedup: true, binary_placeholder: true, ..Default::default() };
+    let (content, out) = round_trip(dir.path(), &opts);
+    assert!(content.contains("same-as=a.png"), "{}", content);
+    assert_eq!(read_tree(out.path()).into_keys().collect::<Vec<_>>(), ["c.txt"]);
+
+    let bundle = write(out.path(), "bundle.md", content.as_bytes());
+    let strict = trxx::RevertOptions {
+        output_dir: Some(out.path().join("strict")),
+        strict: true,
+        only: vec!["b.png".to_string()],
+        ..Default::default()
+    };
+    let err = trxx::revert_files(&[bundle.to_string_lossy().into_owned()], &strict).unwrap_err();
+    assert!(err.to_string().contains("b.png"), "{}", err);
+}
+
+#[test]
+fn content_with_backtick_runs_round_trips() {
+    let nested = b"before\n````\n```rust\nfn main() {}\n```\n````\n`````\nafter ``` inline\n";
+    let dir = tree(&[("notes.md", nested), ("notes.txt", nested), ("fence.rs", b"let s = \"```\";\n```\n")]);
+    assert_round_trip(dir.path(), &PackOptions::default());
+    assert_round_trip(dir.path(), &PackOptions { escape_markdown: false, ..Default::default() });
+}
+
+#[test]
+fn metadata_comment_does_not_break_revert() {
+    let dir = tree(&[("a.txt", b"hello\n")]);
+    let content = assert_round_trip(dir.path(), &PackOptions { metadata: true, ..Default::default() });
+    assert!(content.contains("###  trxx:a.txt\n<!-- size=6 mtime="), "{}", content);
+}
+
+#[test]
+fn preserve_mtime_restores_modification_time() {
+    let dir = tree(&[("a.txt", b"hello\n")]);
+    let mtime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    std::fs::File::options().write(true).open(dir.path().join("a.txt")).unwrap().set_modified(mtime).unwrap();
+
+    let content = trxx::pack(dir.path(), &PackOptions { metadata: true, ..Default::default() }).unwrap();
+    let out = temp_dir();
+    let bundle = write(out.path(), "bundle.md", content.as_bytes());
+    let restored = out.path().join("restored");
+    trxx::revert_files(&[bundle.to_string_lossy().into_owned()], &trxx::RevertOptions {
+        output_dir: Some(restored.clone()),
+        preserve_mtime: true,
+        ..Default::default()
+    }).unwrap();
+    let restored_mtime = std::fs::metadata(restored.join("a.txt")).unwrap().modified().unwrap();
+    let diff = restored_mtime.duration_since(mtime).unwrap_or_else(|e| e.duration());
+    assert!(diff.as_secs() < 1, "{:?}", diff);
+}
+
+#[test]
+fn crlf_files_round_trip_byte_identical() {
+    let dir = tree(&[
+        ("win.txt", b"line1\r\nline2\r\n\r\nlast"),
+        ("mixed.txt", b"lf\ncrlf\r\nlf\n"),
+        ("stray-cr.txt", b"a\r\n\r\r\nb\r\n"),
+        ("doc.md", b"# title\r\n```\r\ncode\r\n```\r\n"),
+    ]);
+    assert_round_trip(dir.path(), &PackOptions::default());
+}
+
+#[test]
+fn packing_twice_is_byte_identical() {
+    let dir = tree(&[
+        ("z.txt", b"zzzz\n"),
+        ("b/file10.rs", b"fn ten() {}\n"),
+        ("a.txt", b"a\n"),
+        ("b/file2.rs", b"fn two() {}\n"),
+        ("B.md", b"# upper\n"),
+    ]);
+    let first = trxx::pack(dir.path(), &PackOptions::default()).unwrap();
+    let second = trxx::pack(dir.path(), &PackOptions::default()).unwrap();
+    assert_eq!(first, second);
+    let headers: Vec<&str> = first.lines().filter_map(|line| line.strip_prefix(trxx::DEFAULT_HEADER_PREFIX)).collect();
+    assert_eq!(headers, ["B.md", "a.txt", "b/file10.rs", "b/file2.rs", "z.txt"]);
+
+    let opts = PackOptions { sort: trxx::SortOrder::Size, ..Default::default() };
+    let by_size = trxx::pack(dir.path(), &opts).unwrap();
+    assert_eq!(by_size, trxx::pack(dir.path(), &opts).unwrap());
+    assert!(by_size.lines().next().unwrap().ends_with("a.txt"), "{}", by_size);
+}
+
+#[test]
+fn empty_files_exist_after_revert() {
+    let dir = tree(&[("pkg/__init__.py", b""), ("assets/.gitkeep", b""), ("empty.md", b""), ("a.txt", b"a\n")]);
+    // .gitkeep 以点开头，需要 hidden 才会打包
+    let (_, out) = round_trip(dir.path(), &PackOptions { hidden: true, ..Default::default() });
+    for path in ["pkg/__init__.py", "assets/.gitkeep", "empty.md"] {
+        let restored = out.path().join(path);
+        assert!(restored.is_file(), "缺少 {}", path);
+        assert_eq!(std::fs::metadata(restored).unwrap().len(), 0);
+    }
+    assert_eq!(read_tree(out.path()), read_tree(dir.path()));
+}
+
+#[test]
+fn empty_dirs_survive_round_trip_only_when_kept() {
+    let dir = tree(&[("src/a.rs", b"fn a() {}\n")]);
+    for empty in ["empty", "nested/deeper/leaf", "src/unused"] {
+        std::fs::create_dir_all(dir.path().join(empty)).unwrap();
+    }
+
+    let (content, out) = round_trip(dir.path(), &PackOptions { keep_empty_dirs: true, ..Default::default() });
+    assert!(content.contains("###  trxx-dir:nested/deeper/leaf/\n"), "{}", content);
+    for empty in ["empty", "nested/deeper/leaf", "src/unused"] {
+        assert!(out.path().join(empty).is_dir(), "缺少目录 {}", empty);
+    }
+    assert_eq!(read_tree(out.path()), read_tree(dir.path()));
+
+    let (content, out) = round_trip(dir.path(), &PackOptions::default());
+    assert!(!content.contains("trxx-dir:"));
+    assert!(!out.path().join("empty").exists());
+}
+
+#[test]
+fn since_delta_contains_only_changed_file() {
+    let dir = tree(&[("a.txt", b"a\n"), ("src/b.rs", b"fn b() {}\n"), ("src/c.rs", b"fn c() {}\n")]);
+    let opts = PackOptions { metadata: true, ..Default::default() };
+    let (full, out) = round_trip(dir.path(), &opts);
+    let bundles = temp_dir();
+    let previous = write(bundles.path(), "full.md", full.as_bytes());
+
+    write(dir.path(), "src/b.rs", b"fn b() { todo!() }\n");
+    let delta = trxx::pack(dir.path(), &PackOptions { since: Some(previous), ..opts }).unwrap();
+    let headers: Vec<&str> = delta.lines().filter_map(|line| line.strip_prefix(trxx::DEFAULT_HEADER_PREFIX)).collect();
+    assert_eq!(headers, ["src/b.rs"]);
+
+    trxx::revert(&delta, out.path()).unwrap();
+    assert_eq!(read_tree(out.path()), read_tree(dir.path()));
+}
+
+#[test]
+fn trailing_newlines_are_restored_exactly() {
+    let dir = tree(&[
+        ("zero.txt", b"text"),
+        ("one.txt", b"text\n"),
+        ("three.txt", b"text\n\n\n"),
+        ("three.rs", b"fn f() {}\n\n\n"),
+        ("zero.md", b"# title"),
+        ("three-crlf.txt", b"text\r\n\r\n\r\n"),
+        ("only-newlines.txt", b"\n\n\n"),
+    ]);
+    assert_round_trip(dir.path(), &PackOptions::default());
+}
+
+#[test]
+fn root_prefix_appears_in_headers_and_reverts_under_prefix() {
+    let dir = tree(&[("a.txt", b"a\n"), ("src/main.rs", b"fn main() {}\n")]);
+    std::fs::create_dir(dir.path().join("empty")).unwrap();
+    let opts = PackOptions { root_prefix: Some("backend".to_string()), keep_empty_dirs: true, ..Default::default() };
+    let (content, out) = round_trip(dir.path(), &opts);
+    let headers: Vec<&str> = content.lines().filter_map(|line| line.strip_prefix(trxx::DEFAULT_HEADER_PREFIX)).collect();
+    assert_eq!(headers, ["backend/a.txt", "backend/src/main.rs"]);
+    assert_eq!(read_tree(&out.path().join("backend")), read_tree(dir.path()));
+    assert!(out.path().join("backend/empty").is_dir());
+    assert_eq!(std::fs::read_dir(out.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn dedup_with_strip_comments_keeps_each_language_intact() {
+    let dir = tree(&[("a.py", b"x = 1 # c\n"), ("b.py", b"x = 1 # c\n"), ("c.rs", b"x = 1 # c\n")]);
+    let opts = PackOptions { dedup: true, strip_comments: true, ..Default::default() };
+    let (content, out) = round_trip(dir.path(), &opts);
+    assert!(content.contains("###  trxx:b.py\n<!-- same-as=a.py -->\n"), "{}", content);
+    assert!(!content.contains("###  trxx:c.rs\n<!-- same-as="), "{}", content);
+    let restored = read_tree(out.path());
+    assert_eq!(restored["b.py"], b"x = 1\n");
+    assert_eq!(restored["c.rs"], b"x = 1 # c\n");
+}
+
+#[test]
+fn pack_honors_output_format() {
+    let dir = tree(&[("a.txt", b"hello\n"), ("src/main.rs", b"fn main() {}\n")]);
+    let json = trxx::pack(dir.path(), &PackOptions { format: OutputFormat::Json, ..Default::default() }).unwrap();
+    let entries: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let paths: Vec<_> = entries.as_array().unwrap().iter().map(|e| e["path"].as_str().unwrap()).collect();
+    assert_eq!(paths, ["a.txt", "src/main.rs"]);
+    for format in [OutputFormat::Tar, OutputFormat::Zip] {
+        assert!(trxx::pack(dir.path(), &PackOptions { format, ..Default::default() }).is_err());
+    }
+}
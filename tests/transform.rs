@@ -0,0 +1,127 @@
+mod common;
+
+use common::*;
+use trxx::PackOptions;
+
+// 打包单个文件，返回它的代码块正文（去掉打包时在首尾加的空行）
+fn packed_body(name: &str, content: &[u8], opts: &PackOptions) -> String {
+    let dir = tree(&[(name, content)]);
+    let packed = trxx::pack(dir.path(), opts).unwrap();
+    let start = packed.find("\n```").unwrap();
+    let body = &packed[start + 1..];
+    let body = &body[body.find('\n').unwrap() + 2..];
+    body[..body.rfind("\n\n```").unwrap()].to_string()
+}
+
+fn stripped(name: &str, content: &str) -> String {
+    packed_body(name, content.as_bytes(), &PackOptions { strip_comments: true, ..Default::default() })
+}
+
+#[test]
+fn strip_comments_removes_rust_comments() {
+    let source = "// header\nfn main() { /* inline */ let s = \"// not a comment\"; } // tail\n/* outer /* inner */ still */\nlet c = '\"';\n";
+    assert_eq!(stripped("a.rs", source), "fn main() {  let s = \"// not a comment\"; }\nlet c = '\"';\n");
+}
+
+#[test]
+fn strip_comments_removes_python_comments() {
+    let source = "#!/usr/bin/env python3\n# comment\nx = 1  # trailing\ny = \"# kept\"\nn = len(s)#tail\n";
+    assert_eq!(stripped("a.py", source), "#!/usr/bin/env python3\nx = 1\ny = \"# kept\"\nn = len(s)#tail\n");
+}
+
+#[test]
+fn strip_comments_keeps_js_regex_literals() {
+    let source = "const re = /https?:\\/\\//g; // strip me\nconst half = a / b; // div\nconst q = /[/]\"/.test(x);\nreturn /a'b/;\n";
+    assert_eq!(
+        stripped("a.js", source),
+        "const re = /https?:\\/\\//g;\nconst half = a / b;\nconst q = /[/]\"/.test(x);\nreturn /a'b/;\n"
+    );
+}
+
+#[test]
+fn unescaped_markdown_passes_through_untouched() {
+    let source = "# Title\n\n```rust\nfn main() {}\n```\n## Section\n";
+    let raw = PackOptions { escape_markdown: false, ..Default::default() };
+    let body = packed_body("README.md", source.as_bytes(), &raw);
+    assert_eq!(body, source);
+    assert_ne!(packed_body("README.md", source.as_bytes(), &PackOptions::default()), source);
+
+    let dir = tree(&[("README.md", source.as_bytes())]);
+    assert_round_trip(dir.path(), &raw);
+}
+
+// 简单的 xorshift 随机数，保证每次运行生成相同的内容
+fn next(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+#[test]
+fn markdown_escaping_round_trips_random_content() {
+    const PIECES: &[&str] = &[
+        "#", "###", "# title", "\\#foo", "\\\\#bar", "\\", "```", "````rust", "\\```", "\\\\```",
+        "###  trxx:x.md", "###  trxx-dir:d/", "  ```", "text", "", "`", "``", "\r",
+    ];
+    let mut state = 0x9e37_79b9_7f4a_7c15;
+    let files: Vec<(String, Vec<u8>)> = (0..200)
+        .map(|i| {
+            let lines = next(&mut state) % 12;
+            let mut content = String::new();
+            for _ in 0..lines {
+                content.push_str(PIECES[(next(&mut state) % PIECES.len() as u64) as usize]);
+                if !next(&mut state).is_multiple_of(4) {
+                    content.push_str(PIECES[(next(&mut state) % PIECES.len() as u64) as usize]);
+                }
+                content.push_str(if next(&mut state).is_multiple_of(3) { "\r\n" } else { "\n" });
+            }
+            if next(&mut state).is_multiple_of(2) {
+                content.pop();
+            }
+            (format!("doc{:03}.md", i), content.into_bytes())
+        })
+        .collect();
+    let files: Vec<(&str, &[u8])> = files.iter().map(|(name, content)| (name.as_str(), content.as_slice())).collect();
+    let dir = tree(&files);
+    assert_round_trip(dir.path(), &PackOptions::default());
+    assert_round_trip(dir.path(), &PackOptions { escape_markdown: false, ..Default::default() });
+}
+
+#[test]
+fn annotate_counts_lines_and_chars_and_reverts() {
+    let dir = tree(&[
+        ("a.txt", "你好 world\nsecond\n".as_bytes()),
+        ("README.md", b"# title\n```\ncode\n```"),
+        ("empty.txt", b""),
+    ]);
+    let content = assert_round_trip(dir.path(), &PackOptions { annotate: true, ..Default::default() });
+    assert!(content.contains("###  trxx:a.txt\n<!-- 2 lines, 16 chars -->\n"), "{}", content);
+    // 按转义之前的原始内容计数
+    assert!(content.contains("###  trxx:README.md\n<!-- 4 lines, 20 chars -->\n"), "{}", content);
+    assert!(content.contains("###  trxx:empty.txt\n<!-- 0 lines, 0 chars -->\n"), "{}", content);
+}
+
+#[test]
+fn line_numbers_are_added_and_stripped_on_revert() {
+    let source = (1..=12).map(|i| format!("line {}\n", i)).collect::<String>() + "\n| pipe\nno newline";
+    let opts = PackOptions { line_numbers: true, ..Default::default() };
+    let body = packed_body("a.txt", source.as_bytes(), &opts);
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(lines[0], " 1 | line 1");
+    assert_eq!(lines[11], "12 | line 12");
+    assert_eq!(lines[12], "13 | ");
+    assert_eq!(lines[14], "15 | no newline");
+
+    let dir = tree(&[("a.txt", source.as_bytes()), ("crlf.txt", b"a\r\nb\r\n"), ("doc.md", b"# t\n```\n")]);
+    assert_round_trip(dir.path(), &opts);
+}
+
+#[test]
+fn strip_comments_handles_escaped_char_literals() {
+    let source = "fn f() {\n    let p = ('\\'','\"'); let s = \"a\"; // note\n    let u = \"http://x\"; }\nlet e = '\\u{1F600}'; // emoji\nlet b = '\\\\'; let t = \"//\";\n";
+    assert_eq!(
+        stripped("a.rs", source),
+        "fn f() {\n    let p = ('\\'','\"'); let s = \"a\";\n    let u = \"http://x\"; }\nlet e = '\\u{1F600}';\nlet b = '\\\\'; let t = \"//\";\n"
+    );
+}